@@ -1,3 +1,12 @@
 use core::arch::global_asm;
 
-global_asm!(include_str!("loader.S"));
+use crate::config::MAX_HARTS;
+
+/// The stack each hart starts on.
+const BOOT_STACK_SIZE: usize = 4096 * 16;
+
+global_asm!(
+    include_str!("loader.S"),
+    MAX_HARTS = const MAX_HARTS,
+    BOOT_STACK_SIZE = const BOOT_STACK_SIZE,
+);
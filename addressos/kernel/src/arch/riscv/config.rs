@@ -1,6 +1,14 @@
 use crate::config::PAGE_SIZE_BITS;
 
+/// Levels of the largest paging mode the kernel is built for: Sv39, Sv48 or Sv57.
+#[cfg(feature = "sv57")]
+pub(crate) const MAX_PAGING_LEVELS: usize = 5;
+#[cfg(all(feature = "sv48", not(feature = "sv57")))]
+pub(crate) const MAX_PAGING_LEVELS: usize = 4;
+#[cfg(not(any(feature = "sv48", feature = "sv57")))]
+pub(crate) const MAX_PAGING_LEVELS: usize = 3;
+
 pub(crate) const PA_WIDTH: usize = 56;
-pub(crate) const VA_WIDTH: usize = 39;
+pub(crate) const VA_WIDTH: usize = PAGE_SIZE_BITS + 9 * MAX_PAGING_LEVELS;
 pub(crate) const PPN_WIDTH: usize = PA_WIDTH - PAGE_SIZE_BITS;
 pub(crate) const VPN_WIDTH: usize = VA_WIDTH - PAGE_SIZE_BITS;
@@ -1,22 +1,59 @@
-use core::fmt::{self, Write};
+use spin::{mutex::SpinMutex, Once};
+
+use crate::{console::Console, mm::address::kernel_virt_to_phys};
+
+/// Bytes handed to the SBI in one call.
+const DBCN_BUFFER_SIZE: usize = 256;
 
 pub(crate) fn console_putchar(c: usize) {
     #[allow(deprecated)]
     sbi_rt::legacy::console_putchar(c);
 }
 
-struct Stdout;
+pub(crate) fn console_getchar() -> usize {
+    #[allow(deprecated)]
+    sbi_rt::legacy::console_getchar()
+}
+
+/// The console of the SBI: whole buffers at once through the Debug Console
+/// extension where the firmware has it, a byte per call otherwise.
+pub(crate) struct SbiConsole;
 
-impl Write for Stdout {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+/// Whether the firmware has the Debug Console extension.
+static DBCN: Once<bool> = Once::new();
+
+/// The SBI takes physical addresses, which only the image has at a fixed
+/// offset. Whatever is printed is copied here first.
+static DBCN_BUFFER: SpinMutex<[u8; DBCN_BUFFER_SIZE]> = SpinMutex::new([0; DBCN_BUFFER_SIZE]);
+
+/// Write `bytes` through the Debug Console extension, returning how many
+/// made it.
+fn dbcn_write(bytes: &[u8]) -> usize {
+    let mut buffer = DBCN_BUFFER.lock();
+    let mut written = 0;
+    for chunk in bytes.chunks(DBCN_BUFFER_SIZE) {
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let pa = kernel_virt_to_phys(buffer.as_ptr() as usize);
+        let mut done = 0;
+        while done < chunk.len() {
+            let ret =
+                sbi_rt::console_write(sbi_rt::Physical::new(chunk.len() - done, pa + done, 0));
+            if ret.error != 0 {
+                return written + done;
+            }
+            done += ret.value;
         }
-        Ok(())
+        written += done;
     }
+    written
 }
 
-#[inline]
-pub(crate) fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
-}
\ No newline at end of file
+impl Console for SbiConsole {
+    fn write_bytes(&self, bytes: &[u8]) {
+        let dbcn = *DBCN.call_once(|| sbi_rt::probe_extension(sbi_rt::Console).is_available());
+        let written = if dbcn { dbcn_write(bytes) } else { 0 };
+        for &c in &bytes[written..] {
+            console_putchar(c as usize);
+        }
+    }
+}
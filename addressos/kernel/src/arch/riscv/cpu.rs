@@ -0,0 +1,39 @@
+use core::arch::asm;
+
+use riscv::register::sip;
+
+/// Point `tp` at the per-CPU area of this hart.
+pub(crate) fn set_cpu_local_base(base: usize) {
+    unsafe {
+        asm!("mv tp, {}", in(reg) base, options(nomem, nostack));
+    }
+}
+
+pub(crate) fn cpu_local_base() -> usize {
+    let base;
+    unsafe {
+        asm!("mv {}, tp", out(reg) base, options(nomem, nostack));
+    }
+    base
+}
+
+/// Ask the SBI to start `hart_id` at physical address `start_addr`, with `opaque` in `a1`.
+/// Fails if the hart does not exist or is already running.
+pub(crate) fn start_hart(hart_id: usize, start_addr: usize, opaque: usize) -> bool {
+    sbi_rt::hart_start(hart_id, start_addr, opaque).error == 0
+}
+
+/// Interrupt the harts set in `hart_mask` with a supervisor software interrupt.
+pub(crate) fn send_ipi(hart_mask: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(hart_mask, 0));
+}
+
+pub(crate) fn clear_ipi() {
+    unsafe { sip::clear_ssoft() };
+}
+
+/// Sleep until an interrupt enabled in `sie` is pending, even with
+/// interrupts off.
+pub(crate) fn wait_for_interrupt() {
+    unsafe { riscv::asm::wfi() };
+}
@@ -1,13 +1,18 @@
-use core::arch::asm;
+use core::{
+    arch::asm,
+    ptr::addr_of_mut,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
 use riscv::register::satp;
 
 use crate::{
-    config::ENTRY_COUNT,
+    arch::config::MAX_PAGING_LEVELS,
+    config::{ENTRY_COUNT, PAGE_SIZE_BITS},
     mm::{
-        address::{PhysAddr, PhysPageNum, VirtAddr},
+        address::{kernel_virt_to_phys, PhysAddr, PhysPageNum, VirtAddr},
         page_table::{PageTableEntryTrait, PageTableFlagsTrait},
     },
 };
@@ -65,6 +70,16 @@ impl PageTableFlagsTrait for PageTableFlags {
         *self
     }
 
+    fn set_accessed(&mut self, accessed: bool) -> Self {
+        self.set(Self::Accessed, accessed);
+        *self
+    }
+
+    fn set_dirty(&mut self, dirty: bool) -> Self {
+        self.set(Self::Dirty, dirty);
+        *self
+    }
+
     fn is_valid(&self) -> bool {
         self.contains(Self::Valid)
     }
@@ -92,6 +107,10 @@ impl PageTableFlagsTrait for PageTableFlags {
     fn is_dirty(&self) -> bool {
         self.contains(Self::Dirty)
     }
+
+    fn is_leaf(&self) -> bool {
+        self.intersects(Self::Read | Self::Write | Self::Execute)
+    }
 }
 
 pub fn tlb_flush(addr: VirtAddr) {
@@ -100,8 +119,100 @@ pub fn tlb_flush(addr: VirtAddr) {
     }
 }
 
+pub fn tlb_flush_all() {
+    unsafe {
+        asm!("sfence.vma", options(nostack));
+    }
+}
+
+/// Flush every non-global translation tagged with `asid`.
+pub fn tlb_flush_asid(asid: usize) {
+    unsafe {
+        asm!("sfence.vma zero, {}", in(reg) asid, options(nostack));
+    }
+}
+
+/// Count the ASID bits implemented by this hart: the unimplemented ones are
+/// hardwired to zero in `satp`.
+pub fn probe_asid_bits() -> usize {
+    const ASID_SHIFT: usize = 44;
+    const ASID_MASK: usize = 0xffff;
+    let old = satp::read().bits();
+    let asid = unsafe {
+        asm!("csrw satp, {}", in(reg) old | ASID_MASK << ASID_SHIFT, options(nostack));
+        let asid = (satp::read().bits() >> ASID_SHIFT) & ASID_MASK;
+        asm!("csrw satp, {}", "sfence.vma", in(reg) old, options(nostack));
+        asid
+    };
+    asid.count_ones() as usize
+}
+
+/// Run `sfence.vma` over `[start, start + size)` on every hart set in `hart_mask`.
+/// A `size` of `usize::MAX` flushes the whole address space.
+pub fn remote_tlb_flush(hart_mask: usize, start: VirtAddr, size: usize) {
+    let ret = sbi_rt::remote_sfence_vma(
+        sbi_rt::HartMask::from_mask_base(hart_mask, 0),
+        usize::from(start),
+        size,
+    );
+    assert_eq!(ret.error, 0, "remote_sfence_vma failed");
+}
+
+/// Levels of the paging mode in use, see [`probe_paging_levels`].
+static PAGING_LEVELS: AtomicUsize = AtomicUsize::new(3);
+
+pub fn paging_levels() -> usize {
+    PAGING_LEVELS.load(Ordering::Relaxed)
+}
+
+/// The `satp` value for the root table at `root` tagged with `asid`, in the
+/// paging mode in use: `MODE` is 8 for Sv39, 9 for Sv48 and 10 for Sv57.
+pub fn make_satp(asid: usize, root: PhysPageNum) -> usize {
+    (paging_levels() + 5) << 60 | asid << 44 | root.0
+}
+
+#[repr(C, align(4096))]
+struct ProbeTable([usize; ENTRY_COUNT]);
+
+/// Roots tried for Sv48 and Sv57. The kernel lives in the last entry at every
+/// level, so each one points there at the root of the mode below and the
+/// kernel stays mapped while a mode is tried.
+static mut PROBE_TABLES: [ProbeTable; 2] =
+    [ProbeTable([0; ENTRY_COUNT]), ProbeTable([0; ENTRY_COUNT])];
+
+/// Pick the largest paging mode up to `MAX_PAGING_LEVELS` this hart supports.
+///
+/// Writing an unsupported mode to `satp` has no effect, so each mode is tried
+/// in turn and read back. Must run on the Sv39 boot page table, before any
+/// other hart is up.
+pub fn probe_paging_levels() -> usize {
+    const SATP_PPN_MASK: usize = (1 << 44) - 1;
+    let old = satp::read().bits();
+    let mut root = old & SATP_PPN_MASK;
+    let mut levels = 3;
+    for next in 4..=MAX_PAGING_LEVELS {
+        let table = unsafe { &mut *addr_of_mut!(PROBE_TABLES[next - 4]) };
+        table.0[ENTRY_COUNT - 1] = root << 10 | PageTableFlags::Valid.bits() as usize;
+        let table_root = kernel_virt_to_phys(table as *const ProbeTable as usize) >> PAGE_SIZE_BITS;
+        let mode = next + 5;
+        let supported = unsafe {
+            asm!("csrw satp, {}", "sfence.vma", in(reg) mode << 60 | table_root, options(nostack));
+            let supported = satp::read().bits() >> 60 == mode;
+            asm!("csrw satp, {}", "sfence.vma", in(reg) old, options(nostack));
+            supported
+        };
+        if !supported {
+            break;
+        }
+        levels = next;
+        root = table_root;
+    }
+    PAGING_LEVELS.store(levels, Ordering::Relaxed);
+    levels
+}
+
 pub fn mm_csr(root_addr:PhysAddr){
-    let stap_bit = 8usize << 60 | root_addr.floor().0;
+    let stap_bit = make_satp(0, root_addr.floor());
     unsafe {
         satp::write(stap_bit);
         asm!("sfence.vma");
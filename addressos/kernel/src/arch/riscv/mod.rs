@@ -1,5 +1,6 @@
 pub(crate) mod boot;
 pub(crate) mod config;
 pub(crate) mod console;
+pub(crate) mod cpu;
 pub(crate) mod mm;
 pub(crate) mod power;
@@ -1,10 +1,21 @@
 use sbi_rt::{system_reset, NoReason, Shutdown, SystemFailure};
+use spin::Once;
+
+/// A driver's way of turning the machine off, tried before the SBI.
+static POWEROFF: Once<fn()> = Once::new();
+
+pub fn set_poweroff(poweroff: fn()) {
+    POWEROFF.call_once(|| poweroff);
+}
 
 pub fn shutdown(failure: bool) -> ! {
     if !failure {
+        if let Some(poweroff) = POWEROFF.get() {
+            poweroff();
+        }
         system_reset(Shutdown, NoReason);
     } else {
         system_reset(Shutdown, SystemFailure);
     }
     unreachable!()
-}
\ No newline at end of file
+}
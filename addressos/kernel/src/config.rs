@@ -6,12 +6,17 @@ use crate::logger::Logger;
 
 pub const USER_STACK_SIZE: usize = PAGE_SIZE * 2;
 pub const KERNEL_STACK_SIZE: usize = PAGE_SIZE * 2;
-// pub const KERNEL_HEAP_SIZE: usize = PAGE_SIZE * 512;
-pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+/// The kernel heap starts with this much, and grows from the frame allocator.
+pub const KERNEL_HEAP_INIT_SIZE: usize = 0x8_0000;
 
+/// Harts we can bring up, each with a boot stack reserved in `loader.S`.
+pub const MAX_HARTS: usize = 8;
+
+/// Where the kernel image is linked: physical `MEMORY_START` shows up here.
 pub const KERNEL_OFFSET: usize = 0xffffffff80000000;
 
-pub const PHYS_OFFSET: usize = 0xffff800000000000;
+/// Base of the linear map of physical memory, the start of the Sv39 upper half.
+pub const PHYS_OFFSET: usize = 0xffff_ffc0_0000_0000;
 pub const ENTRY_COUNT: usize = 512;
 
 pub const PAGE_SIZE: usize = 0x1000;
@@ -26,10 +31,13 @@ pub const DEFAULT_LOG_LEVEL: Level = Level::Error;
 
 pub const REAL_TIME_TASK_PRI: u16 = 100;
 
+/// Ticks of `time` per second when the device tree does not say.
 pub const CLOCK_FREQ: usize = 12500000;
-pub const MEMORY_END: usize = 0x8800_0000;
-
-pub const MMIO: &[(usize, usize)] = &[(0x0010_0000, 0x00_2000)];
+/// Start of RAM, where the firmware is loaded, followed by the kernel image.
+/// The rest of the memory map comes from the device tree.
+pub const MEMORY_START: usize = 0x8000_0000;
+/// RAM disk at the end of memory that user pages are swapped to, 0 for none.
+pub const SWAP_SIZE: usize = 0x200_0000;
 
 pub const LOGGER: Logger = Logger;
 
@@ -38,3 +46,11 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
+
+pub fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT - tid * PAGE_SIZE
+}
+
+pub fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
+    ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
+}
@@ -1,7 +1,57 @@
-use core::fmt::Arguments;
+use core::fmt::{self, Write};
 
-pub(crate) fn print(args: Arguments) {
-    crate::arch::console::print(args);
+use spin::mutex::SpinMutex;
+
+use crate::{
+    arch::console::{console_getchar, SbiConsole},
+    drivers::uart::uart,
+    task::suspend_current_and_run_next,
+};
+
+/// Somewhere the kernel prints to.
+pub(crate) trait Console: Sync {
+    fn write_bytes(&self, bytes: &[u8]);
+}
+
+struct Stdout(&'static dyn Console);
+
+impl Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The SBI until a driver takes over. The lock also keeps the output of harts
+/// printing at the same time from interleaving.
+static STDOUT: SpinMutex<Stdout> = SpinMutex::new(Stdout(&SbiConsole));
+
+/// Print to `console` from now on.
+pub(crate) fn set_console(console: &'static dyn Console) {
+    STDOUT.lock().0 = console;
+}
+
+pub(crate) fn print(args: fmt::Arguments) {
+    STDOUT.lock().write_fmt(args).unwrap();
+}
+
+/// Print raw bytes, which need not be UTF-8.
+pub(crate) fn write_bytes(bytes: &[u8]) {
+    STDOUT.lock().0.write_bytes(bytes);
+}
+
+/// Take a byte typed, waiting until there is one.
+pub(crate) fn getchar() -> u8 {
+    if let Some(uart) = uart() {
+        return uart.read_byte();
+    }
+    loop {
+        match console_getchar() {
+            // nothing typed yet
+            0 | usize::MAX => suspend_current_and_run_next(),
+            c => return c as u8,
+        }
+    }
 }
 
 #[macro_export]
@@ -0,0 +1,64 @@
+//! Per-CPU state. Each hart keeps a pointer to its own [`Cpu`] in `tp`.
+
+use core::{mem::size_of, sync::atomic::AtomicUsize};
+
+use log::info;
+use spin::mutex::SpinMutex;
+
+use crate::{
+    arch::cpu::{cpu_local_base, set_cpu_local_base, start_hart},
+    config::MAX_HARTS,
+    mm::address::kernel_virt_to_phys,
+    task::processor::Processor,
+};
+
+pub struct Cpu {
+    pub processor: SpinMutex<Processor>,
+    /// ASID generation this hart last flushed its TLB for.
+    pub tlb_generation: AtomicUsize,
+    /// When the running task is due to give the hart up, in ticks of `time`.
+    pub slice_end: AtomicUsize,
+}
+
+impl Cpu {
+    const fn new() -> Self {
+        Self {
+            processor: SpinMutex::new(Processor::new()),
+            tlb_generation: AtomicUsize::new(0),
+            slice_end: AtomicUsize::new(usize::MAX),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const CPU_INIT: Cpu = Cpu::new();
+
+static CPUS: [Cpu; MAX_HARTS] = [CPU_INIT; MAX_HARTS];
+
+/// Make `tp` point at the per-CPU area of `hart_id`. Must run first on every hart.
+pub fn init(hart_id: usize) {
+    assert!(hart_id < MAX_HARTS, "hart {} is beyond MAX_HARTS", hart_id);
+    set_cpu_local_base(&CPUS[hart_id] as *const Cpu as usize);
+}
+
+pub fn this_cpu() -> &'static Cpu {
+    unsafe { &*(cpu_local_base() as *const Cpu) }
+}
+
+pub fn hart_id() -> usize {
+    (cpu_local_base() - CPUS.as_ptr() as usize) / size_of::<Cpu>()
+}
+
+/// Start every other hart through the SBI HSM extension. Harts that QEMU does
+/// not emulate simply fail to start.
+pub fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    let boot_hart_id = hart_id();
+    let started = (0..MAX_HARTS)
+        .filter(|&id| id != boot_hart_id)
+        .filter(|&id| start_hart(id, kernel_virt_to_phys(_start_secondary as usize), 0))
+        .count();
+    info!("[kernel] started {} secondary harts", started);
+}
@@ -0,0 +1,14 @@
+mod ram_disk;
+
+pub use self::ram_disk::RamDisk;
+
+pub const BLOCK_SIZE: usize = 512;
+
+/// A device storing data in blocks of [`BLOCK_SIZE`] bytes.
+pub trait BlockDevice: Send + Sync {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+
+    fn num_blocks(&self) -> usize;
+}
@@ -0,0 +1,47 @@
+use crate::mm::address::phys_to_virt;
+
+use super::{BlockDevice, BLOCK_SIZE};
+
+/// A block device over physical memory kept away from the frame allocator.
+pub struct RamDisk {
+    /// Start of the memory in the linear map.
+    start: usize,
+    num_blocks: usize,
+}
+
+impl RamDisk {
+    /// # Safety
+    ///
+    /// `[start_pa, start_pa + size)` must be memory that nothing else uses.
+    pub unsafe fn new(start_pa: usize, size: usize) -> Self {
+        Self {
+            start: phys_to_virt(start_pa),
+            num_blocks: size / BLOCK_SIZE,
+        }
+    }
+
+    fn block(&self, block_id: usize) -> *mut u8 {
+        assert!(
+            block_id < self.num_blocks,
+            "block {} out of range",
+            block_id
+        );
+        (self.start + block_id * BLOCK_SIZE) as *mut u8
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let len = buf.len().min(BLOCK_SIZE);
+        unsafe { core::ptr::copy_nonoverlapping(self.block(block_id), buf.as_mut_ptr(), len) };
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let len = buf.len().min(BLOCK_SIZE);
+        unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), self.block(block_id), len) };
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+}
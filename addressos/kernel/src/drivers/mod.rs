@@ -0,0 +1,30 @@
+pub mod block;
+mod plic;
+mod registry;
+mod rtc;
+mod syscon;
+pub mod uart;
+mod virtio;
+
+pub use self::{
+    plic::{handle_irq, register_irq},
+    registry::{register, Device, Driver},
+};
+
+pub(crate) use self::virtio::VirtioNet;
+
+/// Find the devices in the device tree and bring up those we have a driver
+/// for.
+pub fn init() {
+    register(&plic::PLIC_DRIVER);
+    register(&rtc::RTC_DRIVER);
+    register(&syscon::POWEROFF_DRIVER);
+    register(&uart::UART_DRIVER);
+    register(&virtio::VIRTIO_DRIVER);
+    registry::probe();
+}
+
+/// Whether some task is blocked until a device interrupts.
+pub fn waiting_for_irq() -> bool {
+    uart::has_readers() || crate::net::waiting_for_packets()
+}
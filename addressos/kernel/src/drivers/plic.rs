@@ -0,0 +1,143 @@
+//! The platform-level interrupt controller, which routes the interrupts of
+//! devices to the harts.
+//!
+//! Every hart claims interrupts in its supervisor context. An interrupt is
+//! enabled for all of them, at priority 1 over a threshold of 0, and goes to
+//! whichever claims it first.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use log::warn;
+use spin::{mutex::SpinMutex, Once};
+
+use crate::{config::MAX_HARTS, cpu::hart_id, error::Error, fdt::fdt};
+
+use super::{Device, Driver};
+
+pub(super) static PLIC_DRIVER: Driver = Driver {
+    name: "plic",
+    compatible: &["sifive,plic-1.0.0", "riscv,plic0"],
+    probe,
+};
+
+const PRIORITY: usize = 0x0;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// `interrupts-extended` cause of supervisor external interrupts.
+const SUPERVISOR_EXTERNAL: usize = 9;
+
+static PLIC: Once<Plic> = Once::new();
+
+/// Handlers of the interrupts, by number.
+static IRQ_HANDLERS: SpinMutex<BTreeMap<usize, fn()>> = SpinMutex::new(BTreeMap::new());
+
+struct Plic {
+    base: usize,
+    /// Interrupts are numbered from 1 to this.
+    ndev: usize,
+    /// The supervisor context of each hart.
+    contexts: [Option<usize>; MAX_HARTS],
+}
+
+impl Plic {
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    fn enable(&self, irq: usize) {
+        if irq == 0 || irq > self.ndev {
+            warn!("[kernel] plic: no interrupt {}", irq);
+            return;
+        }
+        unsafe {
+            self.reg(PRIORITY + irq * 4).write_volatile(1);
+            for context in self.contexts.iter().flatten() {
+                let enable = self.reg(ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+                enable.write_volatile(enable.read_volatile() | 1 << (irq % 32));
+            }
+        }
+    }
+
+    fn context_reg(&self, context: usize, offset: usize) -> *mut u32 {
+        self.reg(CONTEXT + context * CONTEXT_STRIDE + offset)
+    }
+}
+
+/// The hart whose local interrupt controller is `phandle`.
+fn hart_of_intc(phandle: usize) -> Option<usize> {
+    let cpu = fdt().find_node("/cpus")?.children().find(|cpu| {
+        cpu.children()
+            .any(|intc| intc.property_usize("phandle") == Some(phandle))
+    })?;
+    cpu.reg().next().map(|(hart, _)| hart)
+}
+
+fn probe(device: &Device) -> Result<(), Error> {
+    let node = &device.node;
+    let mut contexts = [None; MAX_HARTS];
+    // (phandle, cause) of each context in turn
+    let cells: Vec<usize> = node.property_cells("interrupts-extended").collect();
+    for (context, pair) in cells.chunks_exact(2).enumerate() {
+        if pair[1] != SUPERVISOR_EXTERNAL {
+            continue;
+        }
+        if let Some(hart) = hart_of_intc(pair[0]).filter(|&hart| hart < MAX_HARTS) {
+            contexts[hart] = Some(context);
+        }
+    }
+    let plic = Plic {
+        base: device.base()?,
+        ndev: node
+            .property_usize("riscv,ndev")
+            .ok_or(Error::InvalidArgs)?,
+        contexts,
+    };
+    for context in plic.contexts.iter().flatten() {
+        unsafe { plic.context_reg(*context, THRESHOLD).write_volatile(0) };
+    }
+
+    let handlers = IRQ_HANDLERS.lock();
+    let plic = PLIC.call_once(|| plic);
+    for irq in handlers.keys() {
+        plic.enable(*irq);
+    }
+    Ok(())
+}
+
+/// Have `handler` called on interrupt `irq` of the PLIC.
+pub fn register_irq(irq: usize, handler: fn()) {
+    let mut handlers = IRQ_HANDLERS.lock();
+    handlers.insert(irq, handler);
+    // or when the PLIC turns up
+    if let Some(plic) = PLIC.get() {
+        plic.enable(irq);
+    }
+}
+
+/// Serve the interrupts pending for this hart.
+pub fn handle_irq() {
+    let Some(plic) = PLIC.get() else {
+        return;
+    };
+    let Some(context) = plic.contexts[hart_id()] else {
+        return;
+    };
+    let claim = plic.context_reg(context, CLAIM);
+    loop {
+        let irq = unsafe { claim.read_volatile() } as usize;
+        // 0 when another hart took it, or nothing is left
+        if irq == 0 {
+            break;
+        }
+        let handler = IRQ_HANDLERS.lock().get(&irq).copied();
+        match handler {
+            Some(handler) => handler(),
+            None => warn!("[kernel] unexpected interrupt {}", irq),
+        }
+        unsafe { claim.write_volatile(irq as u32) };
+    }
+}
@@ -0,0 +1,82 @@
+//! Drivers, and the devices of the device tree they are bound to.
+//!
+//! A driver names the `compatible` strings it handles. Probing walks the
+//! enabled nodes of the device tree, picks for each the driver of its most
+//! specific `compatible`, maps the registers of the node into `KERNEL_SPACE`
+//! and lets the driver take the device from there.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use log::{info, warn};
+use spin::mutex::SpinMutex;
+
+use crate::{
+    error::Error,
+    fdt::{fdt, Node},
+    mm::memory_set::KERNEL_SPACE,
+};
+
+static DRIVERS: SpinMutex<Vec<&'static Driver>> = SpinMutex::new(Vec::new());
+
+pub struct Driver {
+    pub name: &'static str,
+    /// The devices it handles.
+    pub compatible: &'static [&'static str],
+    /// Bring up a device whose registers are mapped.
+    pub probe: fn(&Device) -> Result<(), Error>,
+}
+
+/// A node of the device tree with its registers mapped.
+pub struct Device {
+    pub node: Node,
+    /// Where the ranges of `reg` show up in the linear map.
+    pub regs: Vec<Range<usize>>,
+}
+
+impl Device {
+    pub(crate) fn new(node: Node) -> Self {
+        let mut kernel_space = KERNEL_SPACE.get().unwrap().lock();
+        let regs = node
+            .reg()
+            .filter(|&(_, size)| size > 0)
+            .map(|(addr, size)| {
+                let start = kernel_space.map_mmio(addr, size);
+                start..start + size
+            })
+            .collect();
+        Self { node, regs }
+    }
+
+    /// The first range of registers, which is all most devices have.
+    pub fn base(&self) -> Result<usize, Error> {
+        self.regs
+            .first()
+            .map(|regs| regs.start)
+            .ok_or(Error::InvalidArgs)
+    }
+}
+
+/// Make `driver` available to the next probe.
+pub fn register(driver: &'static Driver) {
+    DRIVERS.lock().push(driver);
+}
+
+/// Bind every enabled device of the device tree to its driver.
+pub(super) fn probe() {
+    // drivers may register more drivers while probing
+    let drivers = DRIVERS.lock().clone();
+    for node in fdt().nodes().filter(Node::is_enabled) {
+        let Some(driver) = node.compatible().find_map(|compatible| {
+            drivers
+                .iter()
+                .find(|driver| driver.compatible.contains(&compatible))
+        }) else {
+            continue;
+        };
+        match (driver.probe)(&Device::new(node)) {
+            Ok(()) => info!("[kernel] {}: {}", node.name, driver.name),
+            Err(err) => warn!("[kernel] {}: {} failed: {:?}", node.name, driver.name, err),
+        }
+    }
+}
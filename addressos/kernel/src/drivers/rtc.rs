@@ -0,0 +1,26 @@
+//! The Goldfish real-time clock, which sets the wall clock at boot.
+
+use crate::{error::Error, timer::set_realtime_ns};
+
+use super::{Device, Driver};
+
+pub(super) static RTC_DRIVER: Driver = Driver {
+    name: "goldfish-rtc",
+    compatible: &["google,goldfish-rtc"],
+    probe,
+};
+
+/// Nanoseconds since the epoch. Reading the low half latches the high half.
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+fn probe(device: &Device) -> Result<(), Error> {
+    let base = device.base()?;
+    let (low, high) = unsafe {
+        let low = ((base + TIME_LOW) as *const u32).read_volatile();
+        let high = ((base + TIME_HIGH) as *const u32).read_volatile();
+        (low, high)
+    };
+    set_realtime_ns((high as u64) << 32 | low as u64);
+    Ok(())
+}
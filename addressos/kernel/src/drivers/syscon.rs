@@ -0,0 +1,37 @@
+//! Powering off by writing a register of a system controller, as
+//! `syscon-poweroff` describes.
+
+use spin::Once;
+
+use crate::{arch::power, error::Error, fdt::fdt};
+
+use super::{Device, Driver};
+
+pub(super) static POWEROFF_DRIVER: Driver = Driver {
+    name: "syscon-poweroff",
+    compatible: &["syscon-poweroff"],
+    probe: probe_poweroff,
+};
+
+/// The register to write and the value to write to it.
+static POWEROFF: Once<(usize, u32)> = Once::new();
+
+fn probe_poweroff(device: &Device) -> Result<(), Error> {
+    let node = &device.node;
+    let regmap = node
+        .property_usize("regmap")
+        .and_then(|phandle| fdt().find_phandle(phandle))
+        .ok_or(Error::InvalidArgs)?;
+    let offset = node.property_usize("offset").ok_or(Error::InvalidArgs)?;
+    let value = node.property_usize("value").ok_or(Error::InvalidArgs)?;
+    let base = Device::new(regmap).base()?;
+    POWEROFF.call_once(|| (base + offset, value as u32));
+    power::set_poweroff(poweroff);
+    Ok(())
+}
+
+fn poweroff() {
+    if let Some(&(reg, value)) = POWEROFF.get() {
+        unsafe { (reg as *mut u32).write_volatile(value) };
+    }
+}
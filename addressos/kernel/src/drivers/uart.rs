@@ -0,0 +1,164 @@
+//! The NS16550A UART, the console once it is probed.
+//!
+//! Output fills the transmit FIFO, 16 bytes at a time. Input is taken on
+//! interrupt into a ring buffer, where readers block until something is typed.
+
+use spin::{mutex::SpinMutex, Once};
+
+use crate::{
+    console::{set_console, Console},
+    error::Error,
+    sync::WaitQueue,
+    task::block_current_and_run_next,
+};
+
+use super::{register_irq, Device, Driver};
+
+pub(super) static UART_DRIVER: Driver = Driver {
+    name: "ns16550a",
+    compatible: &["ns16550a", "ns16550"],
+    probe,
+};
+
+/// Receive buffer, read.
+const RBR: usize = 0;
+/// Transmit holding register, written.
+const THR: usize = 0;
+const IER: usize = 1;
+/// FIFO control register, written.
+const FCR: usize = 2;
+const MCR: usize = 4;
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR: u8 = 0b11 << 1;
+/// Lets the interrupt out of the chip.
+const MCR_OUT2: u8 = 1 << 3;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+const FIFO_SIZE: usize = 16;
+const RX_BUFFER_SIZE: usize = 256;
+
+static UART: Once<Uart> = Once::new();
+
+pub(crate) struct Uart {
+    base: usize,
+    /// Registers are this far apart, as a power of two.
+    reg_shift: usize,
+    rx: SpinMutex<RxBuffer>,
+}
+
+struct RxBuffer {
+    buf: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    readers: WaitQueue,
+}
+
+impl RxBuffer {
+    fn push(&mut self, byte: u8) {
+        // typed faster than read, drop it
+        if self.len == RX_BUFFER_SIZE {
+            return;
+        }
+        self.buf[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+/// The UART, once probed.
+pub(crate) fn uart() -> Option<&'static Uart> {
+    UART.get()
+}
+
+/// Whether a task is blocked reading the UART.
+pub(super) fn has_readers() -> bool {
+    UART.get()
+        .is_some_and(|uart| !uart.rx.lock().readers.is_empty())
+}
+
+fn probe(device: &Device) -> Result<(), Error> {
+    let irq = device
+        .node
+        .property_usize("interrupts")
+        .ok_or(Error::InvalidArgs)?;
+    let uart = Uart {
+        base: device.base()?,
+        reg_shift: device.node.property_usize("reg-shift").unwrap_or(0),
+        rx: SpinMutex::new(RxBuffer {
+            buf: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            readers: WaitQueue::new(),
+        }),
+    };
+    uart.write_reg(FCR, FCR_ENABLE | FCR_CLEAR);
+    uart.write_reg(MCR, MCR_OUT2);
+    uart.write_reg(IER, IER_RX_AVAILABLE);
+    set_console(UART.call_once(|| uart));
+    register_irq(irq, handle_irq);
+    Ok(())
+}
+
+fn handle_irq() {
+    let uart = UART.get().unwrap();
+    let mut rx = uart.rx.lock();
+    while uart.read_reg(LSR) & LSR_DATA_READY != 0 {
+        let byte = uart.read_reg(RBR);
+        rx.push(byte);
+    }
+    rx.readers.wake_all();
+}
+
+impl Uart {
+    fn reg(&self, reg: usize) -> *mut u8 {
+        (self.base + (reg << self.reg_shift)) as *mut u8
+    }
+
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { self.reg(reg).read_volatile() }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { self.reg(reg).write_volatile(value) }
+    }
+
+    /// Take a byte typed, blocking until there is one.
+    pub(crate) fn read_byte(&self) -> u8 {
+        loop {
+            let mut rx = self.rx.lock();
+            if let Some(byte) = rx.pop() {
+                return byte;
+            }
+            rx.readers.push_current();
+            drop(rx);
+            block_current_and_run_next();
+        }
+    }
+}
+
+impl Console for Uart {
+    fn write_bytes(&self, bytes: &[u8]) {
+        for chunk in bytes.chunks(FIFO_SIZE) {
+            // the whole FIFO is free once the holding register is empty
+            while self.read_reg(LSR) & LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            for &byte in chunk {
+                self.write_reg(THR, byte);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Devices on the virtio MMIO transport, in its legacy and modern versions.
+//!
+//! Each `virtio,mmio` node of the device tree is a slot, which may be empty.
+//! The device in it says what it is through its device ID.
+
+mod net;
+mod queue;
+
+use core::sync::atomic::{fence, Ordering};
+
+use log::info;
+
+use crate::{error::Error, mm::address::PhysAddr};
+
+use super::{Device, Driver};
+
+pub(crate) use self::net::VirtioNet;
+
+pub(super) static VIRTIO_DRIVER: Driver = Driver {
+    name: "virtio-mmio",
+    compatible: &["virtio,mmio"],
+    probe,
+};
+
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES: usize = 0x010;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+/// Where the configuration of the device starts.
+const CONFIG: usize = 0x100;
+
+const MAGIC: u32 = 0x7472_6976;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// Set by modern devices, which must be told the driver is modern too.
+pub(super) const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const DEVICE_ID_NET: u32 = 1;
+
+fn probe(device: &Device) -> Result<(), Error> {
+    let transport = MmioTransport::new(device.base()?)?;
+    let irq = device.node.property_usize("interrupts");
+    match transport.device_id() {
+        // an empty slot
+        0 => Ok(()),
+        DEVICE_ID_NET => net::probe(transport, irq.ok_or(Error::InvalidArgs)?),
+        id => {
+            info!(
+                "[kernel] {}: virtio device {} unsupported",
+                device.node.name, id
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The registers of a device on the MMIO transport.
+pub(super) struct MmioTransport {
+    base: usize,
+    /// 1 for legacy devices, 2 for modern ones.
+    version: u32,
+}
+
+impl MmioTransport {
+    fn new(base: usize) -> Result<Self, Error> {
+        let mut transport = Self { base, version: 0 };
+        if transport.read(MAGIC_VALUE) != MAGIC {
+            return Err(Error::InvalidArgs);
+        }
+        transport.version = transport.read(VERSION);
+        if !(1..=2).contains(&transport.version) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(transport)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.base + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.base + offset) as *mut u32).write_volatile(value) }
+    }
+
+    fn device_id(&self) -> u32 {
+        self.read(DEVICE_ID)
+    }
+
+    /// Reset the device and agree on the features of `supported` it has.
+    /// Returns the features agreed on.
+    pub(super) fn begin_init(&self, supported: u64) -> Result<u64, Error> {
+        self.write(STATUS, 0);
+        self.write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        self.write(DEVICE_FEATURES_SEL, 0);
+        let mut features = self.read(DEVICE_FEATURES) as u64;
+        self.write(DEVICE_FEATURES_SEL, 1);
+        features |= (self.read(DEVICE_FEATURES) as u64) << 32;
+        let features = features & supported;
+
+        self.write(DRIVER_FEATURES_SEL, 0);
+        self.write(DRIVER_FEATURES, features as u32);
+        self.write(DRIVER_FEATURES_SEL, 1);
+        self.write(DRIVER_FEATURES, (features >> 32) as u32);
+
+        if self.version == 1 {
+            self.write(GUEST_PAGE_SIZE, queue::QUEUE_ALIGN as u32);
+        } else {
+            self.write(STATUS, self.read(STATUS) | STATUS_FEATURES_OK);
+            if self.read(STATUS) & STATUS_FEATURES_OK == 0 {
+                return Err(Error::InvalidArgs);
+            }
+        }
+        Ok(features)
+    }
+
+    /// Let the device go, once its queues are set up.
+    pub(super) fn finish_init(&self) {
+        self.write(STATUS, self.read(STATUS) | STATUS_DRIVER_OK);
+    }
+
+    /// Largest size of queue `index`, 0 if there is no such queue.
+    pub(super) fn max_queue_size(&self, index: u16) -> u16 {
+        self.write(QUEUE_SEL, index as u32);
+        self.read(QUEUE_NUM_MAX) as u16
+    }
+
+    /// Hand queue `index` of `size` entries over to the device, with its
+    /// descriptor table, available ring and used ring at the given addresses.
+    pub(super) fn setup_queue(
+        &self,
+        index: u16,
+        size: u16,
+        desc: PhysAddr,
+        avail: PhysAddr,
+        used: PhysAddr,
+    ) {
+        self.write(QUEUE_SEL, index as u32);
+        self.write(QUEUE_NUM, size as u32);
+        if self.version == 1 {
+            // one block, the used ring aligned after the rest
+            self.write(QUEUE_ALIGN, queue::QUEUE_ALIGN as u32);
+            self.write(QUEUE_PFN, (desc.0 / queue::QUEUE_ALIGN) as u32);
+        } else {
+            let write_addr = |low, high, addr: PhysAddr| {
+                self.write(low, addr.0 as u32);
+                self.write(high, (addr.0 >> 32) as u32);
+            };
+            write_addr(QUEUE_DESC_LOW, QUEUE_DESC_HIGH, desc);
+            write_addr(QUEUE_DRIVER_LOW, QUEUE_DRIVER_HIGH, avail);
+            write_addr(QUEUE_DEVICE_LOW, QUEUE_DEVICE_HIGH, used);
+            self.write(QUEUE_READY, 1);
+        }
+    }
+
+    pub(super) fn notify(&self, index: u16) {
+        // the rings must be seen before the device looks at them
+        fence(Ordering::SeqCst);
+        self.write(QUEUE_NOTIFY, index as u32);
+    }
+
+    /// Acknowledge the interrupt of the device.
+    pub(super) fn ack_interrupt(&self) {
+        let status = self.read(INTERRUPT_STATUS);
+        self.write(INTERRUPT_ACK, status);
+    }
+
+    /// Byte `offset` of the configuration of the device.
+    pub(super) fn config_u8(&self, offset: usize) -> u8 {
+        unsafe { ((self.base + CONFIG + offset) as *const u8).read_volatile() }
+    }
+}
@@ -0,0 +1,118 @@
+//! The virtio network device, handed to the network stack once probed.
+//!
+//! Every receive buffer stays with the device until a packet lands in it,
+//! which is then copied out and the buffer given back. Transmit buffers are
+//! taken from a free list and reclaimed once the device has sent them.
+
+use alloc::vec::Vec;
+
+use crate::{drivers::register_irq, error::Error, net};
+
+use super::{queue::VirtQueue, MmioTransport, VIRTIO_F_VERSION_1};
+
+/// The device has a MAC address in its configuration.
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+const QUEUE_RX: u16 = 0;
+const QUEUE_TX: u16 = 1;
+const QUEUE_SIZE: u16 = 16;
+/// Room for a header and an Ethernet frame of the largest MTU.
+const BUF_SIZE: usize = 2048;
+
+/// Used when the device has no address of its own, QEMU's default.
+const DEFAULT_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+pub(crate) struct VirtioNet {
+    transport: MmioTransport,
+    rx: VirtQueue,
+    tx: VirtQueue,
+    /// Transmit buffers the device does not hold.
+    tx_free: Vec<u16>,
+    /// Size of the header before each packet, longer for modern devices.
+    header_len: usize,
+    mac: [u8; 6],
+}
+
+pub(super) fn probe(transport: MmioTransport, irq: usize) -> Result<(), Error> {
+    let features = transport.begin_init(VIRTIO_NET_F_MAC | VIRTIO_F_VERSION_1)?;
+    let mut rx = VirtQueue::new(&transport, QUEUE_RX, QUEUE_SIZE, BUF_SIZE)?;
+    let tx = VirtQueue::new(&transport, QUEUE_TX, QUEUE_SIZE, BUF_SIZE)?;
+
+    let mut mac = DEFAULT_MAC;
+    if features & VIRTIO_NET_F_MAC != 0 {
+        for (i, byte) in mac.iter_mut().enumerate() {
+            *byte = transport.config_u8(i);
+        }
+    }
+    let header_len = if features & VIRTIO_F_VERSION_1 != 0 {
+        12
+    } else {
+        10
+    };
+
+    for id in 0..rx.size() {
+        rx.push(id, BUF_SIZE, true);
+    }
+    transport.finish_init();
+    transport.notify(rx.index());
+
+    let tx_free = (0..tx.size()).collect();
+    net::add_ethernet(VirtioNet {
+        transport,
+        rx,
+        tx,
+        tx_free,
+        header_len,
+        mac,
+    });
+    register_irq(irq, net::handle_irq);
+    Ok(())
+}
+
+impl VirtioNet {
+    pub(crate) fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    pub(crate) fn ack_interrupt(&self) {
+        self.transport.ack_interrupt();
+    }
+
+    /// Take a received packet, if there is one.
+    pub(crate) fn receive_packet(&mut self) -> Option<Vec<u8>> {
+        let (id, len) = self.rx.pop_used()?;
+        let header_len = self.header_len;
+        let buffer = self.rx.buffer(id);
+        let packet = buffer[header_len.min(len)..len.min(buffer.len())].to_vec();
+        self.rx.push(id, BUF_SIZE, true);
+        self.transport.notify(self.rx.index());
+        Some(packet)
+    }
+
+    /// Whether a transmit buffer is free.
+    pub(crate) fn can_send(&mut self) -> bool {
+        self.reclaim_tx();
+        !self.tx_free.is_empty()
+    }
+
+    /// Send a packet of `len` bytes, which `fill` writes. A transmit buffer
+    /// must be free.
+    pub(crate) fn send_packet<R>(&mut self, len: usize, fill: impl FnOnce(&mut [u8]) -> R) -> R {
+        self.reclaim_tx();
+        let id = self.tx_free.pop().expect("no free transmit buffer");
+        let header_len = self.header_len;
+        let buffer = self.tx.buffer(id);
+        // no checksum offload, no segmentation
+        buffer[..header_len].fill(0);
+        let result = fill(&mut buffer[header_len..header_len + len]);
+        self.tx.push(id, header_len + len, false);
+        self.transport.notify(self.tx.index());
+        result
+    }
+
+    fn reclaim_tx(&mut self) {
+        while let Some((id, _)) = self.tx.pop_used() {
+            self.tx_free.push(id);
+        }
+    }
+}
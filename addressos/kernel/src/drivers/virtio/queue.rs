@@ -0,0 +1,176 @@
+//! Split virtqueues, each descriptor of which owns a buffer of its own.
+
+use core::{
+    mem::size_of,
+    slice,
+    sync::atomic::{fence, Ordering},
+};
+
+use crate::{
+    config::PAGE_SIZE,
+    error::Error,
+    mm::{address::PhysAddr, option::VirtMemAllocOption, VirtMemSegment},
+};
+
+use super::MmioTransport;
+
+/// Alignment of the used ring, and the page size legacy devices are told.
+pub(super) const QUEUE_ALIGN: usize = PAGE_SIZE;
+
+/// The device writes to the buffer, rather than reading it.
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// Element of the used ring.
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+pub(super) struct VirtQueue {
+    index: u16,
+    size: u16,
+    /// The descriptor table, the available ring and the used ring.
+    ring: VirtMemSegment,
+    used_offset: usize,
+    /// Buffer `i` belongs to descriptor `i`.
+    buffers: VirtMemSegment,
+    buf_size: usize,
+    /// Entries put in the available ring so far.
+    avail_idx: u16,
+    /// Entries taken from the used ring so far.
+    last_used: u16,
+}
+
+impl VirtQueue {
+    /// Set up queue `index` with up to `size` buffers of `buf_size` bytes.
+    pub(super) fn new(
+        transport: &MmioTransport,
+        index: u16,
+        size: u16,
+        buf_size: usize,
+    ) -> Result<Self, Error> {
+        let size = size.min(transport.max_queue_size(index));
+        if size == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let n = size as usize;
+        let avail_offset = n * size_of::<Descriptor>();
+        let used_offset = (avail_offset + 6 + 2 * n).next_multiple_of(QUEUE_ALIGN);
+        let ring_size = used_offset + 6 + n * size_of::<UsedElem>();
+        let ring = VirtMemAllocOption::new(ring_size.div_ceil(PAGE_SIZE))
+            .set_contiguous(true)
+            .set_align(QUEUE_ALIGN)
+            .alloc_contiguous()?;
+        let buffers = VirtMemAllocOption::new((n * buf_size).div_ceil(PAGE_SIZE))
+            .set_contiguous(true)
+            .alloc_contiguous()?;
+
+        let queue = Self {
+            index,
+            size,
+            ring,
+            used_offset,
+            buffers,
+            buf_size,
+            avail_idx: 0,
+            last_used: 0,
+        };
+        for id in 0..size {
+            let addr = queue.buffers.start_phys_addr().0 + id as usize * buf_size;
+            debug_assert!(addr + buf_size <= queue.buffers.end_phys_addr().0);
+            unsafe {
+                queue.desc(id).write_volatile(Descriptor {
+                    addr: addr as u64,
+                    len: buf_size as u32,
+                    flags: 0,
+                    next: 0,
+                })
+            };
+        }
+        let ring_pa = queue.ring.start_phys_addr().0;
+        transport.setup_queue(
+            index,
+            size,
+            PhysAddr(ring_pa),
+            PhysAddr(ring_pa + avail_offset),
+            PhysAddr(ring_pa + used_offset),
+        );
+        Ok(queue)
+    }
+
+    pub(super) fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub(super) fn size(&self) -> u16 {
+        self.size
+    }
+
+    fn at<T>(&self, offset: usize) -> *mut T {
+        unsafe { self.ring.as_mut_ptr().add(offset) as *mut T }
+    }
+
+    fn desc(&self, id: u16) -> *mut Descriptor {
+        self.at(id as usize * size_of::<Descriptor>())
+    }
+
+    fn avail_offset(&self) -> usize {
+        self.size as usize * size_of::<Descriptor>()
+    }
+
+    /// The buffer of descriptor `id`.
+    pub(super) fn buffer(&mut self, id: u16) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(
+                self.buffers.as_mut_ptr().add(id as usize * self.buf_size),
+                self.buf_size,
+            )
+        }
+    }
+
+    /// Hand the buffer of descriptor `id` to the device, which reads its first
+    /// `len` bytes, or fills it if `device_writes`. The device is not told.
+    pub(super) fn push(&mut self, id: u16, len: usize, device_writes: bool) {
+        let flags = if device_writes { DESC_F_WRITE } else { 0 };
+        unsafe {
+            let desc = self.desc(id);
+            (*desc).len = len.min(self.buf_size) as u32;
+            (*desc).flags = flags;
+            let slot = self.avail_idx % self.size;
+            self.at::<u16>(self.avail_offset() + 4 + 2 * slot as usize)
+                .write_volatile(id);
+            // the entry must be seen before the index covering it
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            self.at::<u16>(self.avail_offset() + 2)
+                .write_volatile(self.avail_idx);
+        }
+    }
+
+    /// Take back a buffer the device is done with: its descriptor, and how
+    /// many bytes the device wrote into it.
+    pub(super) fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used_idx = unsafe { self.at::<u16>(self.used_offset + 2).read_volatile() };
+        if used_idx == self.last_used {
+            return None;
+        }
+        // the element must not be read before the index covering it
+        fence(Ordering::SeqCst);
+        let slot = self.last_used % self.size;
+        let elem = unsafe {
+            self.at::<UsedElem>(self.used_offset + 4 + slot as usize * size_of::<UsedElem>())
+                .read_volatile()
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some((elem.id as u16, elem.len as usize))
+    }
+}
@@ -0,0 +1,304 @@
+//! Reading the flattened device tree the firmware hands to the boot hart.
+//!
+//! Only what the kernel needs is supported: walking the nodes, reading their
+//! properties and `reg`, and the memory reservation block. The tree is never
+//! copied, so it must stay where the firmware put it.
+
+use core::{ops::Range, slice, str};
+
+use log::info;
+use spin::Once;
+
+use crate::mm::address::{phys_to_virt, virt_to_phys};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Deeper nodes are walked through, but their `reg` is not understood.
+const MAX_DEPTH: usize = 16;
+
+/// `#address-cells` and `#size-cells` when a node does not say.
+const DEFAULT_CELLS: (usize, usize) = (2, 1);
+
+static FDT: Once<Fdt> = Once::new();
+
+/// Take the device tree at the physical address `dtb`.
+pub fn init(dtb: usize) {
+    let fdt = unsafe { Fdt::from_ptr(phys_to_virt(dtb) as *const u8) }
+        .expect("no device tree from the firmware");
+    info!("[kernel] device tree at {:#x}, {} bytes", dtb, fdt.size());
+    FDT.call_once(|| fdt);
+}
+
+pub(crate) fn fdt() -> &'static Fdt {
+    FDT.get().unwrap()
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// A big endian number of one or two cells.
+fn read_cells(data: &[u8]) -> usize {
+    data.chunks(4)
+        .fold(0, |value, cell| (value << 32) | be32(cell, 0) as usize)
+}
+
+/// The NUL terminated string at `offset`.
+fn cstr(data: &'static [u8], offset: usize) -> &'static str {
+    let len = data[offset..].iter().position(|&byte| byte == 0).unwrap();
+    str::from_utf8(&data[offset..offset + len]).unwrap_or("")
+}
+
+const fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fdt {
+    data: &'static [u8],
+    structs: usize,
+    strings: usize,
+    reservations: usize,
+}
+
+impl Fdt {
+    /// # Safety
+    ///
+    /// `addr` must point to a device tree which stays there for good.
+    pub(crate) unsafe fn from_ptr(addr: *const u8) -> Option<Self> {
+        let header = slice::from_raw_parts(addr, 40);
+        if be32(header, 0) != FDT_MAGIC {
+            return None;
+        }
+        let data = slice::from_raw_parts(addr, be32(header, 4) as usize);
+        Some(Self {
+            data,
+            structs: be32(data, 8) as usize,
+            strings: be32(data, 12) as usize,
+            reservations: be32(data, 16) as usize,
+        })
+    }
+
+    /// Size of the whole blob.
+    pub(crate) fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Physical addresses the blob occupies.
+    pub(crate) fn range(&self) -> Range<usize> {
+        let start = virt_to_phys(self.data.as_ptr() as usize);
+        start..start + self.size()
+    }
+
+    /// The memory reservation block: physical ranges as (address, size).
+    pub(crate) fn reservations(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.reservations..)
+            .step_by(16)
+            .map(|offset| {
+                (
+                    be64(self.data, offset) as usize,
+                    be64(self.data, offset + 8) as usize,
+                )
+            })
+            .take_while(|&(addr, size)| addr != 0 || size != 0)
+    }
+
+    /// Every node, parents before their children.
+    pub(crate) fn nodes(&self) -> Nodes {
+        Nodes::new(*self, self.structs, DEFAULT_CELLS)
+    }
+
+    /// The node at `path`, such as `/chosen`. A unit address may be left
+    /// out: `/memory` finds `/memory@80000000`.
+    pub(crate) fn find_node(&self, path: &str) -> Option<Node> {
+        let mut node = self.nodes().next()?;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            node = node.children().find(|child| {
+                child.name == component || child.name.split('@').next() == Some(component)
+            })?;
+        }
+        Some(node)
+    }
+
+    /// The node whose `phandle` is `phandle`.
+    pub(crate) fn find_phandle(&self, phandle: usize) -> Option<Node> {
+        self.nodes()
+            .find(|node| node.property_usize("phandle") == Some(phandle))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Node {
+    fdt: Fdt,
+    pub name: &'static str,
+    /// Depth below where the walk that found it started.
+    depth: usize,
+    /// Where its properties start.
+    props: usize,
+    /// `#address-cells` and `#size-cells` of the parent, which `reg` follows.
+    cells: (usize, usize),
+}
+
+impl Node {
+    pub(crate) fn properties(&self) -> impl Iterator<Item = (&'static str, &'static [u8])> {
+        let data = self.fdt.data;
+        let strings = self.fdt.strings;
+        let mut offset = self.props;
+        core::iter::from_fn(move || loop {
+            match be32(data, offset) {
+                FDT_PROP => {
+                    let len = be32(data, offset + 4) as usize;
+                    let name = cstr(data, strings + be32(data, offset + 8) as usize);
+                    let value = &data[offset + 12..offset + 12 + len];
+                    offset += 12 + align4(len);
+                    return Some((name, value));
+                }
+                FDT_NOP => offset += 4,
+                _ => return None,
+            }
+        })
+    }
+
+    pub(crate) fn property(&self, name: &str) -> Option<&'static [u8]> {
+        self.properties()
+            .find(|&(prop, _)| prop == name)
+            .map(|(_, value)| value)
+    }
+
+    /// A property holding a number of one or two cells.
+    pub(crate) fn property_usize(&self, name: &str) -> Option<usize> {
+        self.property(name)
+            .filter(|value| value.len() == 4 || value.len() == 8)
+            .map(read_cells)
+    }
+
+    /// A property holding a list of cells.
+    pub(crate) fn property_cells(&self, name: &str) -> impl Iterator<Item = usize> {
+        self.property(name)
+            .unwrap_or(&[])
+            .chunks_exact(4)
+            .map(|cell| be32(cell, 0) as usize)
+    }
+
+    /// A property holding a string.
+    pub(crate) fn property_str(&self, name: &str) -> Option<&'static str> {
+        let value = self.property(name)?;
+        str::from_utf8(value.strip_suffix(&[0]).unwrap_or(value)).ok()
+    }
+
+    /// The strings of `compatible`, the most specific first.
+    pub(crate) fn compatible(&self) -> impl Iterator<Item = &'static str> {
+        self.property("compatible")
+            .unwrap_or(&[])
+            .split(|&byte| byte == 0)
+            .filter(|compatible| !compatible.is_empty())
+            .filter_map(|compatible| str::from_utf8(compatible).ok())
+    }
+
+    /// Whether the device is there to be used, as `status` says.
+    pub(crate) fn is_enabled(&self) -> bool {
+        matches!(self.property_str("status"), None | Some("okay" | "ok"))
+    }
+
+    /// The (address, size) pairs of `reg`.
+    pub(crate) fn reg(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (address_cells, size_cells) = self.cells;
+        let entry = (address_cells + size_cells) * 4;
+        self.property("reg")
+            .unwrap_or(&[])
+            .chunks_exact(entry.max(4))
+            .map(move |cells| {
+                let (addr, size) = cells.split_at(address_cells * 4);
+                (read_cells(addr), read_cells(size))
+            })
+    }
+
+    /// The direct children.
+    pub(crate) fn children(&self) -> impl Iterator<Item = Node> {
+        let cells = self.child_cells();
+        Nodes::new(self.fdt, self.props, cells).filter(|node| node.depth == 0)
+    }
+
+    /// `#address-cells` and `#size-cells` for the `reg` of the children.
+    fn child_cells(&self) -> (usize, usize) {
+        (
+            self.property_usize("#address-cells")
+                .unwrap_or(DEFAULT_CELLS.0),
+            self.property_usize("#size-cells")
+                .unwrap_or(DEFAULT_CELLS.1),
+        )
+    }
+}
+
+/// A walk through the nodes from some point of the tree, depth first, which
+/// ends with the node it started in.
+pub(crate) struct Nodes {
+    fdt: Fdt,
+    offset: usize,
+    depth: usize,
+    /// The cells the nodes at each depth follow.
+    cells: [(usize, usize); MAX_DEPTH],
+}
+
+impl Nodes {
+    fn new(fdt: Fdt, offset: usize, cells: (usize, usize)) -> Self {
+        Self {
+            fdt,
+            offset,
+            depth: 0,
+            cells: [cells; MAX_DEPTH],
+        }
+    }
+}
+
+impl Iterator for Nodes {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        let data = self.fdt.data;
+        loop {
+            let token = be32(data, self.offset);
+            self.offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(data, self.offset);
+                    self.offset += align4(name.len() + 1);
+                    let node = Node {
+                        fdt: self.fdt,
+                        name,
+                        depth: self.depth,
+                        props: self.offset,
+                        cells: self.cells[self.depth.min(MAX_DEPTH - 1)],
+                    };
+                    self.depth += 1;
+                    if self.depth < MAX_DEPTH {
+                        self.cells[self.depth] = node.child_cells();
+                    }
+                    return Some(node);
+                }
+                FDT_END_NODE => {
+                    if self.depth == 0 {
+                        // stay put, the walk is over
+                        self.offset -= 4;
+                        return None;
+                    }
+                    self.depth -= 1;
+                }
+                FDT_PROP => self.offset += 8 + align4(be32(data, self.offset) as usize),
+                FDT_NOP => {}
+                _ => {
+                    self.offset -= 4;
+                    return None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use alloc::sync::Arc;
+
+use crate::{mm::page_table::UserBuffer, net::Socket};
+
+mod ram;
+mod stdio;
+
+pub(crate) use self::ram::RamFile;
+pub(crate) use self::stdio::{Stdin, Stdout};
+
+/// Where `lseek` moves the offset of a file to.
+pub(crate) enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
+
+/// Anything a process can hold in its file descriptor table.
+pub(crate) trait File: Send + Sync {
+    fn readable(&self) -> bool;
+
+    fn writable(&self) -> bool;
+
+    fn read(&self, buf: UserBuffer) -> usize;
+
+    fn write(&self, buf: UserBuffer) -> usize;
+
+    /// The data behind the file, if it can be mapped into memory.
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        None
+    }
+
+    /// Move the offset the next read or write starts at, returning the new
+    /// one. `None` if the file has no offset or it would be negative.
+    fn seek(&self, _pos: SeekFrom) -> Option<usize> {
+        None
+    }
+
+    /// The file as a socket, if it is one.
+    fn socket(&self) -> Option<&dyn Socket> {
+        None
+    }
+}
+
+/// Data stored somewhere, which can be read and written at any offset.
+pub(crate) trait Inode: Send + Sync {
+    /// A number no other inode has, which names its pages in the page cache.
+    fn id(&self) -> usize;
+
+    fn size(&self) -> usize;
+
+    /// Read from `offset` into `buf`, returning how many bytes were read.
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// Write `buf` at `offset`, returning how many bytes were written.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+}
@@ -0,0 +1,131 @@
+//! Files living in memory only, which processes create with `memfd_create`.
+//!
+//! `read` and `write` go through the page cache, so they agree with the
+//! mappings of the file.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::mutex::SpinMutex;
+
+use crate::mm::{page_cache, page_table::UserBuffer};
+
+use super::{File, Inode, SeekFrom};
+
+/// Ids are never reused, since pages of a dropped inode may stay cached.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct RamInode {
+    id: usize,
+    data: SpinMutex<Vec<u8>>,
+}
+
+impl Inode for RamInode {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn size(&self) -> usize {
+        self.data.lock().len()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.data.lock();
+        let Some(rest) = data.get(offset..) else {
+            return 0;
+        };
+        let len = buf.len().min(rest.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        len
+    }
+
+    /// Grows the file, zero-filled, if `offset` is past its end. Writes
+    /// nothing if there is no memory for that.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut data = self.data.lock();
+        let Some(end) = offset.checked_add(buf.len()) else {
+            return 0;
+        };
+        if end > data.len() {
+            let len = data.len();
+            if data.try_reserve(end - len).is_err() {
+                return 0;
+            }
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(buf);
+        buf.len()
+    }
+}
+
+/// An open file over a [`RamInode`], read and written from its offset on.
+pub(crate) struct RamFile {
+    inode: Arc<RamInode>,
+    offset: SpinMutex<usize>,
+}
+
+impl RamFile {
+    /// A new, empty file.
+    pub(crate) fn new() -> Self {
+        Self {
+            inode: Arc::new(RamInode {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                data: SpinMutex::new(Vec::new()),
+            }),
+            offset: SpinMutex::new(0),
+        }
+    }
+}
+
+impl File for RamFile {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.lock();
+        let mut read = 0;
+        for buffer in buf.buffers.iter_mut() {
+            let len = page_cache::read(&*self.inode, *offset, buffer);
+            *offset += len;
+            read += len;
+            if len < buffer.len() {
+                break;
+            }
+        }
+        read
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut offset = self.offset.lock();
+        let mut written = 0;
+        for buffer in buf.buffers.iter() {
+            let len = page_cache::write(&*self.inode, *offset, buffer);
+            *offset += len;
+            written += len;
+            if len < buffer.len() {
+                break;
+            }
+        }
+        written
+    }
+
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        Some(Arc::clone(&self.inode) as Arc<dyn Inode>)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Option<usize> {
+        let mut offset = self.offset.lock();
+        let new_offset = match pos {
+            SeekFrom::Start(to) => Some(to),
+            SeekFrom::Current(by) => offset.checked_add_signed(by),
+            SeekFrom::End(by) => self.inode.size().checked_add_signed(by),
+        }?;
+        *offset = new_offset;
+        Some(new_offset)
+    }
+}
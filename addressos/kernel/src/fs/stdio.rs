@@ -0,0 +1,54 @@
+use crate::{
+    console::{getchar, write_bytes},
+    mm::page_table::UserBuffer,
+};
+
+use super::File;
+
+pub(crate) struct Stdin;
+
+pub(crate) struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    /// Wait for one typed byte, however long `buf` is.
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let Some(first) = buf.buffers.iter_mut().find_map(|buffer| buffer.first_mut()) else {
+            return 0;
+        };
+        *first = getchar();
+        1
+    }
+
+    fn write(&self, _buf: UserBuffer) -> usize {
+        panic!("Cannot write to stdin!");
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, _buf: UserBuffer) -> usize {
+        panic!("Cannot read from stdout!");
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        for buffer in buf.buffers.iter() {
+            write_bytes(buffer);
+        }
+        buf.len()
+    }
+}
@@ -1,3 +1,7 @@
+use core::arch::global_asm;
+
+global_asm!(include_str!("link_app.S"));
+
 pub fn get_num_app() -> usize {
     extern "C" {
         fn _num_app();
@@ -1,6 +1,6 @@
 use log::{Level, Log, Record};
 
-use crate::{config::LOGGER, println};
+use crate::{config::LOGGER, println, timer::get_time_ns};
 
 pub struct Logger;
 
@@ -20,10 +20,13 @@ impl Log for Logger {
 
         (self.enabled(record.metadata())).then(|| {
             let color = level2color(record.level());
+            let now = get_time_ns() / 1000;
 
             println!(
-                "\u{1B}[{}m[{:^5}] {}\u{1B}[0m",
+                "\u{1B}[{}m[{:>5}.{:06}] [{:^5}] {}\u{1B}[0m",
                 color,
+                now / 1_000_000,
+                now % 1_000_000,
                 record.level(),
                 record.args(),
             );
@@ -6,22 +6,27 @@
 #![feature(ptr_sub_ptr)]
 #![feature(const_ptr_sub_ptr)]
 #![feature(trivial_bounds)]
+#![feature(asm_const)]
 
 #[macro_use]
 extern crate alloc;
 
-use arch::power::shutdown;
 use log::info;
 
 mod arch;
 mod config;
 #[macro_use]
 mod console;
+mod cpu;
+mod drivers;
 pub mod error;
+mod fdt;
 pub mod ffi;
+mod fs;
 pub mod loader;
 mod logger;
 mod mm;
+mod net;
 mod panic;
 mod sync;
 pub mod task;
@@ -30,13 +35,37 @@ pub mod trap;
 pub mod syscall;
 
 #[no_mangle]
-extern "C" fn start_kernel() -> ! {
+extern "C" fn start_kernel(hart_id: usize, dtb: usize) -> ! {
     clear_bss();
+    cpu::init(hart_id);
     logger::init();
-    info!("[kernel] Hello, world!");
+    info!("[kernel] Hello, world! boot hart {}", hart_id);
+    fdt::init(dtb);
+    timer::init();
     mm::init();
+    net::init();
+    drivers::init();
     println!("[kernel] back to world!");
-    shutdown(false)
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
+    trap::enable_external_interrupt();
+    task::add_initial_tasks();
+    cpu::start_secondary_harts();
+    task::run_tasks();
+}
+
+/// Entry of the harts started by [`cpu::start_secondary_harts`].
+#[no_mangle]
+extern "C" fn start_kernel_secondary(hart_id: usize) -> ! {
+    cpu::init(hart_id);
+    mm::init_secondary();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
+    trap::enable_external_interrupt();
+    info!("[kernel] hart {} is online", hart_id);
+    task::run_tasks();
 }
 
 fn clear_bss() {
@@ -2,12 +2,10 @@ use core::ops::Add;
 
 use bytemuck::{Pod, Zeroable};
 
-use crate::config::{PAGE_SIZE, PAGE_SIZE_BITS};
-
-const PA_WIDTH_SV39: usize = 56;
-const VA_WIDTH_SV39: usize = 39;
-const PPN_WIDTH_SV39: usize = PA_WIDTH_SV39 - PAGE_SIZE_BITS;
-const VPN_WIDTH_SV39: usize = VA_WIDTH_SV39 - PAGE_SIZE_BITS;
+use crate::{
+    arch::config::{PA_WIDTH, PPN_WIDTH, VA_WIDTH, VPN_WIDTH},
+    config::{KERNEL_OFFSET, MEMORY_START, PAGE_SIZE, PAGE_SIZE_BITS, PHYS_OFFSET},
+};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Pod, Zeroable, Debug)]
 #[repr(C)]
@@ -27,25 +25,25 @@ pub struct VirtPageNum(pub usize);
 
 impl From<usize> for PhysAddr {
     fn from(value: usize) -> Self {
-        Self(value & ((1 << PA_WIDTH_SV39) - 1))
+        Self(value & ((1 << PA_WIDTH) - 1))
     }
 }
 
 impl From<usize> for PhysPageNum {
     fn from(value: usize) -> Self {
-        Self(value & ((1 << PPN_WIDTH_SV39) - 1))
+        Self(value & ((1 << PPN_WIDTH) - 1))
     }
 }
 
 impl From<usize> for VirtAddr {
     fn from(value: usize) -> Self {
-        Self(value & ((1 << VA_WIDTH_SV39) - 1))
+        Self(value & ((1 << VA_WIDTH) - 1))
     }
 }
 
 impl From<usize> for VirtPageNum {
     fn from(value: usize) -> Self {
-        Self(value & ((1 << VPN_WIDTH_SV39) - 1))
+        Self(value & ((1 << VPN_WIDTH) - 1))
     }
 }
 
@@ -63,8 +61,8 @@ impl From<PhysPageNum> for usize {
 
 impl From<VirtAddr> for usize {
     fn from(addr: VirtAddr) -> Self {
-        if addr.0 >= (1 << (VA_WIDTH_SV39 - 1)) {
-            addr.0 | (!((1 << VA_WIDTH_SV39) - 1))
+        if addr.0 >= (1 << (VA_WIDTH - 1)) {
+            addr.0 | (!((1 << VA_WIDTH) - 1))
         } else {
             addr.0
         }
@@ -188,14 +186,28 @@ impl VirtPageNum {
 impl PhysPageNum {
     pub fn get_bytes_array(&self) -> &'static mut [u8] {
         let pa: PhysAddr = (*self).into();
-        unsafe { core::slice::from_raw_parts_mut(pa.0 as *mut u8, 4096) }
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(pa.0) as *mut u8, 4096) }
     }
     pub fn get_mut<T>(&self) -> &'static mut T {
         let pa: PhysAddr = (*self).into();
-        unsafe { (pa.0 as *mut T).as_mut().unwrap() }
+        unsafe { (phys_to_virt(pa.0) as *mut T).as_mut().unwrap() }
     }
 }
 
+/// Where the kernel sees physical address `pa` through the linear map.
+pub const fn phys_to_virt(pa: usize) -> usize {
+    pa + PHYS_OFFSET
+}
+
+/// The physical address behind `va`, which must be in the linear map.
+pub const fn virt_to_phys(va: usize) -> usize {
+    va - PHYS_OFFSET
+}
+
+/// The physical address behind `va`, which must be in the kernel image.
+pub const fn kernel_virt_to_phys(va: usize) -> usize {
+    va - KERNEL_OFFSET + MEMORY_START
+}
 
 pub trait HasPhysAddr {
     fn phys_addr(&self) -> PhysAddr;
@@ -0,0 +1,103 @@
+//! Address space identifiers, handed out in generations.
+//!
+//! When every ASID of a generation is taken, a new generation starts over from
+//! the first ASID and each hart flushes its whole TLB before it loads an
+//! address space again. Address spaces notice that their ASID is from an old
+//! generation the next time they are activated and pick a new one.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::info;
+use spin::mutex::SpinMutex;
+
+use crate::{arch::mm::tlb_flush_all, cpu::this_cpu};
+
+/// Bits of an [`Asid`] value below the generation.
+const ASID_FIELD_BITS: usize = 16;
+const ASID_MASK: usize = (1 << ASID_FIELD_BITS) - 1;
+
+/// ASID bits implemented by the harts, 0 if ASIDs are not supported.
+static ASID_BITS: AtomicUsize = AtomicUsize::new(0);
+
+struct AsidAllocator {
+    generation: usize,
+    /// Next free ASID of the current generation. ASID 0 belongs to the kernel.
+    next: usize,
+}
+
+static ASID_ALLOCATOR: SpinMutex<AsidAllocator> = SpinMutex::new(AsidAllocator {
+    generation: 1,
+    next: 1,
+});
+
+/// Generation of the allocator, readable without taking its lock.
+static GENERATION: AtomicUsize = AtomicUsize::new(1);
+
+impl AsidAllocator {
+    /// Returns `generation << ASID_FIELD_BITS | asid`.
+    fn alloc(&mut self) -> usize {
+        let bits = ASID_BITS.load(Ordering::Relaxed);
+        if bits == 0 {
+            return self.generation << ASID_FIELD_BITS;
+        }
+        if self.next == 1 << bits {
+            self.generation += 1;
+            self.next = 1;
+            GENERATION.store(self.generation, Ordering::Release);
+            info!("[kernel] ASID generation {} begins", self.generation);
+        }
+        self.next += 1;
+        self.generation << ASID_FIELD_BITS | (self.next - 1)
+    }
+}
+
+/// Set up the allocator with the ASID bits probed on the boot hart.
+pub fn init(bits: usize) {
+    let bits = bits.min(ASID_FIELD_BITS);
+    ASID_BITS.store(bits, Ordering::Relaxed);
+    info!("[kernel] {} ASID bits", bits);
+}
+
+pub fn enabled() -> bool {
+    ASID_BITS.load(Ordering::Relaxed) != 0
+}
+
+/// Flush the TLB of this hart if a new generation began since its last flush,
+/// since its entries may be tagged with ASIDs that now mean something else.
+pub fn sync_hart() {
+    let generation = GENERATION.load(Ordering::Acquire);
+    let cpu = this_cpu();
+    if cpu.tlb_generation.swap(generation, Ordering::AcqRel) != generation {
+        tlb_flush_all();
+    }
+}
+
+/// ASID of one address space. The kernel space keeps ASID 0 forever.
+#[derive(Debug)]
+pub(crate) struct Asid(AtomicUsize);
+
+impl Asid {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::Acquire) & ASID_MASK
+    }
+
+    fn generation(&self) -> usize {
+        self.0.load(Ordering::Acquire) >> ASID_FIELD_BITS
+    }
+
+    /// Make sure the ASID belongs to the current generation.
+    pub(crate) fn refresh(&self) {
+        if self.generation() == GENERATION.load(Ordering::Acquire) {
+            return;
+        }
+        let mut allocator = ASID_ALLOCATOR.lock();
+        // another hart may have refreshed it meanwhile
+        if self.generation() != allocator.generation {
+            self.0.store(allocator.alloc(), Ordering::Release);
+        }
+    }
+}
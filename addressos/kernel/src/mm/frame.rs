@@ -6,13 +6,24 @@ use bytemuck::{bytes_of, Pod, Zeroable};
 use crate::config::PAGE_SIZE;
 
 use super::{
-    address::{HasPhysAddr, PhysAddr, PhysPageNum},
+    address::{phys_to_virt, HasPhysAddr, PhysAddr, PhysPageNum},
     frame_allocator,
 };
 
 #[derive(Debug)]
 pub(crate) struct VirtMemFrame {
-    frame_index: Arc<PhysPageNum>,
+    frame_index: Arc<FrameInner>,
+}
+
+/// The frame itself, freed when the last clone of its [`VirtMemFrame`] is
+/// dropped.
+#[derive(Debug)]
+struct FrameInner(PhysPageNum);
+
+impl Drop for FrameInner {
+    fn drop(&mut self) {
+        frame_allocator::dealloc(self.0);
+    }
 }
 
 impl Clone for VirtMemFrame {
@@ -32,30 +43,36 @@ impl HasPhysAddr for VirtMemFrame {
 impl VirtMemFrame {
     pub(crate) fn new(frame_index: PhysPageNum) -> Self {
         VirtMemFrame {
-            frame_index: frame_index.into(),
+            frame_index: Arc::new(FrameInner(frame_index)),
         }
     }
 
     pub(crate) fn frame_index(&self) -> PhysPageNum {
-        *self.frame_index
+        self.frame_index.0
+    }
+
+    /// Whether the frame is referenced from elsewhere too, such as a
+    /// [`UserBuffer`](super::page_table::UserBuffer) pinning it.
+    pub(crate) fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.frame_index) > 1
     }
 
     pub(crate) fn start_phys_addr(&self) -> PhysAddr {
-        (*self.frame_index).into()
+        self.frame_index.0.into()
     }
 
     pub(crate) fn end_phys_addr(&self) -> PhysAddr {
-        (*self.frame_index + 1).into()
+        (self.frame_index.0 + 1).into()
     }
 
     pub(crate) fn as_ptr(&self) -> *const u8 {
         let addr = self.start_phys_addr();
-        addr.0 as *const u8
+        phys_to_virt(addr.0) as *const u8
     }
 
     pub(crate) fn as_mut_ptr(&self) -> *mut u8 {
         let addr = self.start_phys_addr();
-        addr.0 as *mut u8
+        phys_to_virt(addr.0) as *mut u8
     }
 
     pub fn copy_from_frame(&self, src: &Self) {
@@ -79,12 +96,74 @@ impl<'a> VirtMemFrame {
     }
 }
 
-impl Drop for VirtMemFrame {
+/// Physically contiguous frames, such as the buffers of a device doing DMA.
+/// They are freed all at once when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct VirtMemSegment {
+    inner: Arc<SegmentInner>,
+}
+
+#[derive(Debug)]
+struct SegmentInner {
+    start: PhysPageNum,
+    nframes: usize,
+}
+
+impl Drop for SegmentInner {
     fn drop(&mut self) {
-        if Arc::strong_count(&self.frame_index) == 1 {
-            frame_allocator::dealloc(self.frame_index());
+        frame_allocator::dealloc_contiguous(self.start, self.nframes);
+    }
+}
+
+impl HasPhysAddr for VirtMemSegment {
+    fn phys_addr(&self) -> PhysAddr {
+        self.start_phys_addr()
+    }
+}
+
+impl VirtMemSegment {
+    /// Take over the `nframes` frames from `start` given by
+    /// [`frame_allocator::alloc_contiguous`].
+    pub(crate) fn new(start: PhysPageNum, nframes: usize) -> Self {
+        Self {
+            inner: Arc::new(SegmentInner { start, nframes }),
         }
     }
+
+    pub(crate) fn nframes(&self) -> usize {
+        self.inner.nframes
+    }
+
+    /// Size in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.inner.nframes * PAGE_SIZE
+    }
+
+    pub(crate) fn start_phys_addr(&self) -> PhysAddr {
+        self.inner.start.into()
+    }
+
+    pub(crate) fn end_phys_addr(&self) -> PhysAddr {
+        (self.inner.start + self.inner.nframes).into()
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        phys_to_virt(self.start_phys_addr().0) as *const u8
+    }
+
+    pub(crate) fn as_mut_ptr(&self) -> *mut u8 {
+        phys_to_virt(self.start_phys_addr().0) as *mut u8
+    }
+}
+
+impl<'a> VirtMemSegment {
+    pub fn reader(&'a self) -> VirtMemReader<'a> {
+        unsafe { VirtMemReader::from_raw_parts(self.as_ptr(), self.size()) }
+    }
+
+    pub fn writer(&'a self) -> VirtMemWriter<'a> {
+        unsafe { VirtMemWriter::from_raw_parts_mut(self.as_mut_ptr(), self.size()) }
+    }
 }
 
 pub struct VirtMemReader<'a> {
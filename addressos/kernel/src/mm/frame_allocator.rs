@@ -1,42 +1,149 @@
-use crate::{config::MEMORY_END, mm::address::PhysAddr};
+use crate::{config::PAGE_SIZE, cpu::hart_id};
 
-use super::{address::PhysPageNum, frame::VirtMemFrame};
+use super::{address::PhysPageNum, frame::VirtMemFrame, memory_map::memory_map, page_cache, swap};
+
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use alloc::vec::Vec;
-use buddy_system_allocator::LockedFrameAllocator;
+use buddy_system_allocator::{FrameAllocator, LockedFrameAllocator};
 use spin::Once;
 
 pub(super) static FRAME_ALLOCATOR: Once<LockedFrameAllocator> = Once::new();
 
+/// The hart holding [`FRAME_ALLOCATOR`], or `NO_HOLDER`. The heap grows from
+/// the allocator, which allocates from the heap: a hart must not wait for it
+/// from inside itself.
+static HOLDER: AtomicUsize = AtomicUsize::new(NO_HOLDER);
+const NO_HOLDER: usize = usize::MAX;
+
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static USED_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static HEAP_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Frames managed by the allocator.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total: usize,
+    /// Handed out, including to the heap.
+    pub used: usize,
+    /// Given to the kernel heap for good.
+    pub heap: usize,
+}
+
+/// Run `f` on the allocator, locked by this hart.
+fn with_allocator<R>(f: impl FnOnce(&mut FrameAllocator) -> R) -> R {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    HOLDER.store(hart_id(), Ordering::Relaxed);
+    let ret = f(&mut allocator);
+    HOLDER.store(NO_HOLDER, Ordering::Relaxed);
+    ret
+}
+
+/// Allocate a frame, dropping unused file pages or swapping user pages out to
+/// make room if needed.
 pub(crate) fn alloc() -> Option<VirtMemFrame> {
-    FRAME_ALLOCATOR
-        .get()
-        .unwrap()
-        .lock()
-        .alloc(1)
-        .map(|start| VirtMemFrame::new(start.into()))
+    loop {
+        // the allocator must not be locked while reclaiming, which frees frames
+        let frame = with_allocator(|allocator| allocator.alloc(1));
+        if let Some(start) = frame {
+            USED_FRAMES.fetch_add(1, Ordering::Relaxed);
+            return Some(VirtMemFrame::new(start.into()));
+        }
+        if page_cache::shrink() == 0 && !swap::reclaim() {
+            return None;
+        }
+    }
 }
 
 pub(crate) fn dealloc(frame_index: PhysPageNum) {
-    FRAME_ALLOCATOR
-        .get()
-        .unwrap()
-        .lock()
-        .dealloc(frame_index.into(), 1);
+    with_allocator(|allocator| allocator.dealloc(frame_index.into(), 1));
+    USED_FRAMES.fetch_sub(1, Ordering::Relaxed);
 }
 
-pub fn init_frame_allocator() {
-    extern "C" {
-        fn ekernel();
+/// Allocate `count` physically contiguous frames, the first one aligned to
+/// `align` frames, a power of two. Unused file pages are dropped to make room
+/// if needed, but nothing is swapped out, which would hardly help.
+pub(crate) fn alloc_contiguous(count: usize, align: usize) -> Option<PhysPageNum> {
+    debug_assert!(align.is_power_of_two());
+    // a buddy block is aligned to its size
+    let size = count.max(align).next_power_of_two();
+    loop {
+        let start = with_allocator(|allocator| {
+            let start = allocator.alloc(size)?;
+            dealloc_range(allocator, start + count, size - count);
+            Some(start)
+        });
+        if let Some(start) = start {
+            USED_FRAMES.fetch_add(count, Ordering::Relaxed);
+            return Some(PhysPageNum(start));
+        }
+        if page_cache::shrink() == 0 {
+            return None;
+        }
     }
-    println!("ekernel: 0x{:x?}", ekernel as usize);
+}
 
-    let allocator = LockedFrameAllocator::<32>::new();
+/// Free `count` frames from `start`, allocated by [`alloc_contiguous`].
+pub(crate) fn dealloc_contiguous(start: PhysPageNum, count: usize) {
+    with_allocator(|allocator| dealloc_range(allocator, start.0, count));
+    USED_FRAMES.fetch_sub(count, Ordering::Relaxed);
+}
 
-    allocator.lock().add_frame(
-        PhysAddr::from(ekernel as usize).ceil().0,
-        PhysAddr::from(MEMORY_END).floor().0,
-    );
+/// Give `count` frames from `start` back as the largest blocks the buddy
+/// allocator takes: aligned to their size, which is a power of two.
+fn dealloc_range(allocator: &mut FrameAllocator, mut start: usize, count: usize) {
+    let end = start + count;
+    while start < end {
+        let size =
+            (1 << start.trailing_zeros().min(usize::BITS - 1)).min(1 << (end - start).ilog2());
+        allocator.dealloc(start, size);
+        start += size;
+    }
+}
+
+/// Take `count` contiguous frames, aligned to `count`, for the kernel heap.
+///
+/// Unlike [`alloc`], this never reclaims: the heap may run out with any lock
+/// held. Unless `wait`, it gives up if the allocator is busy, possibly with an
+/// allocation of its own. Even if `wait`, it never waits for this very hart.
+pub(super) fn alloc_for_heap(count: usize, wait: bool) -> Option<PhysPageNum> {
+    let allocator = FRAME_ALLOCATOR.get()?;
+    let start = loop {
+        if let Some(mut allocator) = allocator.try_lock() {
+            HOLDER.store(hart_id(), Ordering::Relaxed);
+            let start = allocator.alloc(count);
+            HOLDER.store(NO_HOLDER, Ordering::Relaxed);
+            break start?;
+        }
+        if !wait || HOLDER.load(Ordering::Relaxed) == hart_id() {
+            return None;
+        }
+        spin_loop();
+    };
+    USED_FRAMES.fetch_add(count, Ordering::Relaxed);
+    HEAP_FRAMES.fetch_add(count, Ordering::Relaxed);
+    Some(PhysPageNum(start))
+}
+
+pub(crate) fn stats() -> FrameStats {
+    FrameStats {
+        total: TOTAL_FRAMES.load(Ordering::Relaxed),
+        used: USED_FRAMES.load(Ordering::Relaxed),
+        heap: HEAP_FRAMES.load(Ordering::Relaxed),
+    }
+}
+
+/// Hand the usable RAM of the [`memory_map`] over to the allocator.
+pub fn init_frame_allocator() {
+    let allocator = LockedFrameAllocator::<32>::new();
+    for range in memory_map().usable.iter() {
+        let (start, end) = (range.start / PAGE_SIZE, range.end / PAGE_SIZE);
+        allocator.lock().add_frame(start, end);
+        TOTAL_FRAMES.fetch_add(end - start, Ordering::Relaxed);
+    }
 
     FRAME_ALLOCATOR.call_once(|| allocator);
 }
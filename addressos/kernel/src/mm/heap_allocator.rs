@@ -1,37 +1,139 @@
-use buddy_system_allocator::LockedHeap;
+//! The kernel heap.
+//!
+//! Small objects come from the caches of [`slab`](super::slab), larger ones
+//! from a buddy heap. The heap starts out in a static array, just enough to
+//! bring the frame allocator up, and then grows by blocks of frames taken from
+//! it. Those frames are never given back.
 
-use crate::config::KERNEL_HEAP_SIZE;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{null_mut, NonNull},
+};
+
+use buddy_system_allocator::Heap;
+use spin::mutex::SpinMutex;
+
+use crate::config::{KERNEL_HEAP_INIT_SIZE, PAGE_SIZE};
+
+use super::{address::phys_to_virt, frame_allocator, slab};
+
+/// Below this many free bytes, the heap grows ahead of time. The frame
+/// allocator allocates from the heap itself, so it must never find it empty.
+const HEAP_RESERVE: usize = 0x1_0000;
+/// The heap grows by at least this much at a time.
+const HEAP_GROW_SIZE: usize = 0x10_0000;
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap<32> = LockedHeap::<32>::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap(SpinMutex::new(Heap::<32>::empty()));
+
+static mut HEAP_SPACE: [u8; KERNEL_HEAP_INIT_SIZE] = [0; KERNEL_HEAP_INIT_SIZE];
+
+struct KernelHeap(SpinMutex<Heap<32>>);
 
-static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
+impl KernelHeap {
+    /// Add a block of at least `size` bytes taken from the frame allocator.
+    /// Unless `wait`, give up if the frame allocator is busy. Either way, give
+    /// up if it is busy on this hart, which then allocates from the heap.
+    fn grow(&self, size: usize, wait: bool) -> bool {
+        let size = size.max(HEAP_GROW_SIZE).next_power_of_two();
+        // the block is aligned to its size, so it serves any alignment up to it
+        let Some(start) = frame_allocator::alloc_for_heap(size / PAGE_SIZE, wait) else {
+            return false;
+        };
+        let start = phys_to_virt(start.0 * PAGE_SIZE);
+        unsafe { self.0.lock().add_to_heap(start, start + size) };
+        true
+    }
+
+    /// A page to cut into slab objects: a frame, or a page of the buddy heap
+    /// if the frame allocator is busy, maybe allocating from the very cache
+    /// asking for it.
+    fn alloc_slab_page(&self) -> *mut u8 {
+        match frame_allocator::alloc_for_heap(1, false) {
+            Some(frame) => phys_to_virt(frame.0 * PAGE_SIZE) as *mut u8,
+            None => self.alloc_blocks(Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap()),
+        }
+    }
+
+    fn alloc_blocks(&self, layout: Layout) -> *mut u8 {
+        loop {
+            let mut heap = self.0.lock();
+            if let Ok(ptr) = heap.alloc(layout) {
+                let low = heap.stats_total_bytes() - heap.stats_alloc_actual() < HEAP_RESERVE;
+                // growing takes the frame allocator, which allocates from the heap
+                drop(heap);
+                if low {
+                    // fails harmlessly when the frame allocator is the one allocating
+                    self.grow(HEAP_GROW_SIZE, false);
+                }
+                return ptr.as_ptr();
+            }
+            drop(heap);
+            if !self.grow(layout.size().max(layout.align()), true) {
+                return null_mut();
+            }
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match slab::cache_for(layout) {
+            Some(cache) => cache.alloc(|| self.alloc_slab_page()),
+            None => self.alloc_blocks(layout),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match slab::cache_for(layout) {
+            Some(cache) => cache.dealloc(ptr),
+            None => self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout),
+        }
+    }
+}
 
 pub(crate) fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
-            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
+            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_INIT_SIZE);
+    }
+}
+
+/// Bytes in the kernel heap.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub total: usize,
+    /// In use, which is more than asked for since blocks are powers of two.
+    pub used: usize,
+    pub requested: usize,
+}
+
+pub(crate) fn stats() -> HeapStats {
+    let heap = HEAP_ALLOCATOR.0.lock();
+    HeapStats {
+        total: heap.stats_total_bytes(),
+        used: heap.stats_alloc_actual(),
+        requested: heap.stats_alloc_user(),
     }
 }
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Heap allocation error, layout = {:?}", layout);
+    panic!(
+        "Heap allocation error, layout = {:?}, {}",
+        layout,
+        super::stats()
+    );
 }
 
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
-    extern "C" {
-        fn sbss();
-        fn ebss();
-    }
-    let bss_range = sbss as usize..ebss as usize;
     let a = Box::new(5);
     assert_eq!(*a, 5);
-    assert!(bss_range.contains(&(a.as_ref() as *const _ as usize)));
     drop(a);
     let mut v: Vec<usize> = Vec::new();
     for i in 0..500 {
@@ -40,7 +142,6 @@ pub fn heap_test() {
     for (i, val) in v.iter().take(500).enumerate() {
         assert_eq!(*val, i);
     }
-    assert!(bss_range.contains(&(v.as_ptr() as usize)));
     drop(v);
     println!("heap_test passed!");
 }
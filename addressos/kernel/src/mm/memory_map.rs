@@ -0,0 +1,134 @@
+//! The physical memory map, as the device tree describes it.
+//!
+//! RAM comes from the `/memory` nodes. Out of it go the firmware and the
+//! kernel image, the device tree itself, the memory reservation block, the
+//! `/reserved-memory` nodes and the initrd. What is left is usable, but for
+//! the swap RAM disk carved from its end.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use log::info;
+use spin::Once;
+
+use crate::{
+    config::{MEMORY_START, PAGE_SIZE, SWAP_SIZE},
+    fdt::fdt,
+};
+
+use super::address::kernel_virt_to_phys;
+
+static MEMORY_MAP: Once<MemoryMap> = Once::new();
+
+pub(crate) struct MemoryMap {
+    /// Every range of RAM, sorted.
+    pub ram: Vec<Range<usize>>,
+    /// Page aligned ranges of RAM the frame allocator manages.
+    pub usable: Vec<Range<usize>>,
+    /// Where user pages are swapped to.
+    pub swap: Option<Range<usize>>,
+}
+
+pub(crate) fn memory_map() -> &'static MemoryMap {
+    MEMORY_MAP.get().unwrap()
+}
+
+/// Take `hole` out of `ranges`.
+fn subtract(ranges: &mut Vec<Range<usize>>, hole: &Range<usize>) {
+    *ranges = ranges
+        .iter()
+        .flat_map(|range| {
+            [
+                range.start..range.end.min(hole.start),
+                range.start.max(hole.end)..range.end,
+            ]
+        })
+        .filter(|range| !range.is_empty())
+        .collect();
+}
+
+pub fn init() {
+    extern "C" {
+        fn ekernel();
+    }
+    let fdt = fdt();
+
+    let mut ram: Vec<Range<usize>> = fdt
+        .nodes()
+        .filter(|node| node.property_str("device_type") == Some("memory"))
+        .flat_map(|node| node.reg())
+        .map(|(addr, size)| addr..addr + size)
+        .filter(|range| !range.is_empty())
+        .collect();
+    ram.sort_by_key(|range| range.start);
+
+    // the image is linked high but loaded right after the firmware
+    let mut reserved = vec![
+        (
+            MEMORY_START..kernel_virt_to_phys(ekernel as usize),
+            "firmware and kernel",
+        ),
+        (fdt.range(), "device tree"),
+    ];
+    reserved.extend(
+        fdt.reservations()
+            .map(|(addr, size)| (addr..addr + size, "reserved")),
+    );
+    if let Some(node) = fdt.find_node("/reserved-memory") {
+        for child in node.children() {
+            reserved.extend(
+                child
+                    .reg()
+                    .map(|(addr, size)| (addr..addr + size, child.name)),
+            );
+        }
+    }
+    let initrd = fdt.find_node("/chosen").and_then(|chosen| {
+        Some(
+            chosen.property_usize("linux,initrd-start")?
+                ..chosen.property_usize("linux,initrd-end")?,
+        )
+    });
+    if let Some(initrd) = initrd.filter(|initrd| !initrd.is_empty()) {
+        reserved.push((initrd, "initrd"));
+    }
+
+    let mut usable = ram.clone();
+    for (range, _) in reserved.iter() {
+        subtract(&mut usable, range);
+    }
+    let mut usable: Vec<_> = usable
+        .into_iter()
+        .map(|range| range.start.next_multiple_of(PAGE_SIZE)..range.end & !(PAGE_SIZE - 1))
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    let swap = usable
+        .iter_mut()
+        .rev()
+        .find(|range| SWAP_SIZE > 0 && range.len() >= SWAP_SIZE)
+        .map(|range| {
+            range.end -= SWAP_SIZE;
+            range.end..range.end + SWAP_SIZE
+        });
+    usable.retain(|range| !range.is_empty());
+    if let Some(swap) = swap.clone() {
+        reserved.push((swap, "swap"));
+    }
+
+    for range in ram.iter() {
+        info!("[kernel] RAM [{:#x}, {:#x})", range.start, range.end);
+    }
+    for (range, name) in reserved.iter() {
+        info!(
+            "[kernel]   reserved [{:#x}, {:#x}): {}",
+            range.start, range.end, name
+        );
+    }
+    info!(
+        "[kernel]   usable: {} MiB",
+        usable.iter().map(|range| range.len()).sum::<usize>() >> 20
+    );
+
+    MEMORY_MAP.call_once(|| MemoryMap { ram, usable, swap });
+}
@@ -1,3 +1,5 @@
+use core::fmt::{self, Debug, Formatter};
+
 use alloc::{
     borrow::ToOwned,
     collections::{btree_map::Entry, BTreeMap},
@@ -8,9 +10,13 @@ use log::{info, warn};
 use spin::{mutex::SpinMutex, once::Once};
 
 use crate::{
-    arch::mm::{mm_csr, PageTableEntry, PageTableFlags},
-    config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE},
+    arch::mm::{
+        make_satp, mm_csr, paging_levels, probe_asid_bits, tlb_flush, PageTableEntry,
+        PageTableFlags,
+    },
+    config::{PAGE_SIZE, TRAMPOLINE},
     error::Error,
+    fs::Inode,
     mm::{
         address::VirtPageNum,
         is_page_aligned,
@@ -19,10 +25,15 @@ use crate::{
 };
 
 use super::{
-    address::{PhysAddr, VirtAddr},
+    address::{kernel_virt_to_phys, phys_to_virt, virt_to_phys, PhysAddr, VirtAddr},
+    asid,
     frame::{VirtMemFrame, VirtMemReader, VirtMemWriter},
+    memory_map::memory_map,
     option::VirtMemAllocOption,
-    page_table::PageTable,
+    page_cache,
+    page_table::{page_size_at, PageTable},
+    shm::ShmAttachment,
+    swap::{swap_area, SwapArea, SwapSlot},
 };
 
 extern "C" {
@@ -47,16 +58,53 @@ pub struct MapArea {
     pub size: usize,
     pub map_type: MapType,
     pub mapper: BTreeMap<VirtAddr, VirtMemFrame>,
+    /// Copies of pages on the swap device. A page only here is swapped out; one
+    /// also in `mapper` came back in and its copy is good until it gets dirty.
+    pub slots: BTreeMap<VirtAddr, SwapSlot>,
+    /// The file mapped by the area, if it is not anonymous memory.
+    pub backing: Option<FileBacking>,
+}
+
+/// The part of a file an area maps, from `offset` on.
+#[derive(Clone)]
+pub struct FileBacking {
+    pub inode: Arc<dyn Inode>,
+    pub offset: usize,
+    /// Whether writes go to the file (`MAP_SHARED`), or to copies of the pages
+    /// made on the first write (`MAP_PRIVATE`).
+    pub shared: bool,
+}
+
+impl Debug for FileBacking {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBacking")
+            .field("inode", &self.inode.id())
+            .field("offset", &self.offset)
+            .field("shared", &self.shared)
+            .finish()
+    }
+}
+
+impl FileBacking {
+    /// The index in the file of the page at `va` of an area starting at `start`.
+    fn page_index(&self, start: VirtAddr, va: VirtAddr) -> usize {
+        (self.offset + va.0 - start.0) / PAGE_SIZE
+    }
 }
 
 pub struct MemorySet {
     pub pt: PageTable<PageTableEntry>,
     areas: BTreeMap<VirtAddr, MapArea>,
+    /// The shared memory segments mapped here, by the start of their area.
+    shm: BTreeMap<VirtAddr, ShmAttachment>,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
-    Identical,
+    /// The kernel image seen at [`KERNEL_OFFSET`](crate::config::KERNEL_OFFSET).
+    Kernel,
+    /// Physical memory seen through [`PHYS_OFFSET`](crate::config::PHYS_OFFSET).
+    Linear,
     Framed,
 }
 
@@ -64,16 +112,30 @@ impl Clone for MapArea {
     fn clone(&self) -> Self {
         let mut mapper = BTreeMap::new();
         for (&va, old) in &self.mapper {
+            // both see the same page cache
+            if self.backing.as_ref().is_some_and(|backing| backing.shared) {
+                mapper.insert(va, old.clone());
+                continue;
+            }
             let new = VirtMemAllocOption::new(1).alloc_single().unwrap();
             new.copy_from_frame(old);
             mapper.insert(va, new.clone());
         }
+        for (&va, slot) in &self.slots {
+            if let Entry::Vacant(e) = mapper.entry(va) {
+                let new = VirtMemAllocOption::new(1).alloc_single().unwrap();
+                swap_area().unwrap().read_page(slot, &new);
+                e.insert(new);
+            }
+        }
         Self {
             start_va: self.start_va,
             size: self.size,
             flags: self.flags,
             map_type: self.map_type,
             mapper,
+            slots: BTreeMap::new(),
+            backing: self.backing.clone(),
         }
     }
 }
@@ -83,6 +145,20 @@ impl MapArea {
         self.size
     }
 
+    /// Pages of user memory areas may go to the swap device. Others, such as
+    /// trap contexts, are accessed by the kernel at any time.
+    pub fn is_swappable(&self) -> bool {
+        self.map_type == MapType::Framed && self.flags.is_accessible_by_user()
+    }
+
+    /// Write the page at `va` back to the file, if the area maps one shared.
+    fn write_back(&self, va: VirtAddr) {
+        if let Some(backing) = self.backing.as_ref().filter(|backing| backing.shared) {
+            let index = backing.page_index(self.start_va, va);
+            page_cache::write_back(&*backing.inode, index, &self.mapper[&va]);
+        }
+    }
+
     pub fn new_with_frames(
         start_va: VirtAddr,
         size: usize,
@@ -108,6 +184,8 @@ impl MapArea {
             size,
             map_type,
             mapper: BTreeMap::new(),
+            slots: BTreeMap::new(),
+            backing: None,
         };
         let mut current_va = start_va;
         let page_size = size / PAGE_SIZE;
@@ -137,6 +215,8 @@ impl MapArea {
             size,
             map_type,
             mapper: BTreeMap::new(),
+            slots: BTreeMap::new(),
+            backing: None,
         }
     }
 
@@ -172,6 +252,8 @@ impl MapArea {
             if current_start_address >= va.0 && current_start_address < va.0 + PAGE_SIZE {
                 let offset = current_start_address - va.0;
                 let _ = pa.writer().skip(offset).write(&mut buf_reader);
+                // the copy on the swap device is stale now
+                self.slots.remove(va);
                 if !buf_reader.has_remain() {
                     return;
                 }
@@ -203,6 +285,7 @@ impl MemorySet {
         Self {
             pt: page_table,
             areas: BTreeMap::new(),
+            shm: BTreeMap::new(),
         }
     }
 
@@ -210,7 +293,7 @@ impl MemorySet {
         self.pt
             .map(
                 VirtAddr::from(TRAMPOLINE),
-                PhysAddr::from(strampoline as usize),
+                PhysAddr::from(kernel_virt_to_phys(strampoline as usize)),
                 PageTableFlags::new()
                     .set_valid(true)
                     .set_readable(true)
@@ -254,7 +337,7 @@ impl MemorySet {
             VirtAddr(stext as usize),
             etext as usize - stext as usize,
             rxflag,
-            MapType::Identical,
+            MapType::Kernel,
         );
 
         println!(
@@ -270,7 +353,7 @@ impl MemorySet {
             VirtAddr(srodata as usize),
             erodata as usize - srodata as usize,
             rflag,
-            MapType::Identical,
+            MapType::Kernel,
         );
 
         println!(
@@ -286,7 +369,7 @@ impl MemorySet {
             VirtAddr(sdata as usize),
             edata as usize - sdata as usize,
             rwflag,
-            MapType::Identical,
+            MapType::Kernel,
         );
 
         println!(
@@ -302,7 +385,7 @@ impl MemorySet {
             VirtAddr(sbss_with_stack as usize),
             ebss as usize - sbss_with_stack as usize,
             rwflag,
-            MapType::Identical,
+            MapType::Kernel,
         );
 
         println!(
@@ -314,31 +397,47 @@ impl MemorySet {
 
         println!("mapping physical memory");
 
-        let mem_area = MapArea::new(
-            VirtAddr(ekernel as usize),
-            MEMORY_END - ekernel as usize,
-            rwflag,
-            MapType::Identical,
-        );
-
-        println!(
-            "physical memory mapped from {:#x?} to {:#x?}",
-            ekernel as usize, MEMORY_END
-        );
-
-        memory_set.map(mem_area);
-
-        println!("mapping memory-mapped registers");
-
-        for (start, size) in MMIO.iter() {
-            let mmio_area = MapArea::new(VirtAddr(*start), *size, rwflag, MapType::Identical);
-
-            memory_set.map(mmio_area);
+        for range in memory_map().ram.iter() {
+            let start = range.start & !(PAGE_SIZE - 1);
+            let end = range.end.next_multiple_of(PAGE_SIZE);
+            let mem_area = MapArea::new(
+                VirtAddr::from(phys_to_virt(start)),
+                end - start,
+                rwflag,
+                MapType::Linear,
+            );
+
+            println!(
+                "physical memory [{:#x?}, {:#x?}) mapped from {:#x?}",
+                start,
+                end,
+                phys_to_virt(start)
+            );
+
+            memory_set.map(mem_area);
         }
 
         memory_set
     }
 
+    /// Map the device registers at physical `[start, start + size)` into the
+    /// linear map, leaving the pages already there alone, and return the
+    /// address they show up at.
+    pub fn map_mmio(&mut self, start: usize, size: usize) -> usize {
+        let flags = PageTableFlags::new()
+            .set_valid(true)
+            .set_readable(true)
+            .set_writable(true);
+        let end = (start + size).next_multiple_of(PAGE_SIZE);
+        for pa in (start & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+            let va = VirtAddr::from(phys_to_virt(pa));
+            if self.pt.translate(va).is_err() {
+                self.pt.map(va, PhysAddr::from(pa), flags).unwrap();
+            }
+        }
+        phys_to_virt(start)
+    }
+
     pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
         let mut memory_set = Self::new();
         // map trampoline
@@ -370,89 +469,76 @@ impl MemorySet {
                     flag.set_executable(true);
                 }
 
-                let map_area = MapArea::new_with_frames(
-                    start_va,
-                    usize::from(end_va) - usize::from(start_va),
+                let area_start_va: VirtAddr = start_va.floor().into();
+                let area_end_va: VirtAddr = end_va.ceil().into();
+                let area_size = usize::from(area_end_va) - usize::from(area_start_va);
+
+                let mut map_area = MapArea::new_with_frames(
+                    area_start_va,
+                    area_size,
                     flag,
                     MapType::Framed,
-                    VirtMemAllocOption::new(
-                        (usize::from(end_va) - usize::from(start_va)) / PAGE_SIZE,
-                    )
-                    .alloc()
-                    .unwrap(),
+                    VirtMemAllocOption::new(area_size / PAGE_SIZE)
+                        .alloc()
+                        .unwrap(),
+                );
+
+                // Write through the area itself: read-only segments must still be loaded.
+                map_area.write_data(
+                    start_va.0,
+                    &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
                 );
 
                 max_end_vpn = end_va.ceil();
 
                 memory_set.map(map_area);
-
-                memory_set
-                    .write_bytes(
-                        start_va.into(),
-                        &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
-                    )
-                    .unwrap();
             }
         }
-        // map user stack with U flags
+        // the user stacks of the threads start above a guard page
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-
-        let user_stack_area = MapArea::new_with_frames(
-            user_stack_bottom.into(),
-            user_stack_top - user_stack_bottom,
-            PageTableFlags::new()
-                .set_accessible_by_user(true)
-                .set_readable(true)
-                .set_writable(true)
-                .set_valid(true),
-            MapType::Framed,
-            VirtMemAllocOption::new((user_stack_top - user_stack_bottom) / PAGE_SIZE)
-                .alloc()
-                .unwrap(),
-        );
-
-        memory_set.map(user_stack_area);
-
-        let trampoline_area = MapArea::new_with_frames(
-            TRAP_CONTEXT.into(),
-            TRAMPOLINE - TRAP_CONTEXT,
-            PageTableFlags::new()
-                .set_readable(true)
-                .set_writable(true)
-                .set_valid(true),
-            MapType::Framed,
-            VirtMemAllocOption::new((TRAMPOLINE - TRAP_CONTEXT) / PAGE_SIZE)
-                .alloc()
-                .unwrap(),
-        );
-
-        memory_set.map(trampoline_area);
+        let mut user_stack_base: usize = max_end_va.into();
+        user_stack_base += PAGE_SIZE;
 
         (
             memory_set,
-            user_stack_top,
+            user_stack_base,
             elf.header.pt2.entry_point() as usize,
         )
     }
 
     pub fn map(&mut self, area: MapArea) {
         match area.map_type {
-            MapType::Identical => {
+            MapType::Kernel => {
                 if area.size > 0 {
                     (area.start_va.0..area.start_va.0 + area.size)
                         .step_by(PAGE_SIZE)
                         .for_each(|va| {
                             //info!("mapping {:#x?}", va);
-                            self.pt
-                                .map(VirtAddr::from(va), PhysAddr::from(va), area.flags)
-                                .unwrap();
+                            let va = VirtAddr::from(va);
+                            let pa = PhysAddr::from(kernel_virt_to_phys(usize::from(va)));
+                            self.pt.map(va, pa, area.flags).unwrap();
                         });
                 }
             }
+            MapType::Linear => {
+                // use the largest pages that fit, 4 KiB ones only at unaligned ends
+                let end = area.start_va.0 + area.size;
+                let mut va = area.start_va.0;
+                while va < end {
+                    let pa = virt_to_phys(usize::from(VirtAddr::from(va)));
+                    let level = (1..=paging_levels())
+                        .rev()
+                        .find(|&level| {
+                            let size = page_size_at(level);
+                            va % size == 0 && pa % size == 0 && end - va >= size
+                        })
+                        .unwrap();
+                    self.pt
+                        .map_huge(VirtAddr::from(va), PhysAddr::from(pa), area.flags, level)
+                        .unwrap();
+                    va += page_size_at(level);
+                }
+            }
             MapType::Framed => {
                 if area.size > 0 {
                     if let Entry::Vacant(e) = self.areas.entry(area.start_va) {
@@ -485,11 +571,236 @@ impl MemorySet {
         false
     }
 
+    /// Resolve a page fault of a user `access` (`Read`, `Write` or `Execute`)
+    /// at `va`, bringing the page in from the swap device, or zero filling it
+    /// on first touch, into `frame`.
+    ///
+    /// The caller allocates `frame` before locking the process, so that pages
+    /// of the process itself can be swapped out to make room.
+    pub fn handle_page_fault(
+        &mut self,
+        va: VirtAddr,
+        access: PageTableFlags,
+        frame: VirtMemFrame,
+    ) -> Result<(), Error> {
+        let page = VirtAddr::from(va.floor());
+        let area = self
+            .areas
+            .range_mut(..=page)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| area.is_swappable() && page.0 < area.start_va.0 + area.size)
+            .ok_or(Error::PageFault)?;
+        if !area.flags.contains(access) {
+            return Err(Error::PageFault);
+        }
+
+        if let Some(cached) = area.mapper.get(&page) {
+            let mut flags = self
+                .pt
+                .translate(page)
+                .map_err(|_| Error::PageFault)?
+                .flags();
+            if access.contains(PageTableFlags::Write) && !flags.is_writable() {
+                // the first write to a private page of a file, which is still
+                // the one in the page cache
+                frame.copy_from_frame(cached);
+                let mut flags = area.flags;
+                flags.set_accessed(true);
+                flags.set_dirty(true);
+                self.pt
+                    .remap(page, frame.start_phys_addr(), flags)
+                    .map_err(|_| Error::PageFault)?;
+                self.pt.flush_tlb();
+                area.mapper.insert(page, frame);
+                return Ok(());
+            }
+            // another thread brought it in first, or the hart leaves the
+            // `Accessed` and `Dirty` bits to us
+            flags.set_accessed(true);
+            if access.contains(PageTableFlags::Write) {
+                flags.set_dirty(true);
+            }
+            self.pt
+                .update_flags(page, flags)
+                .map_err(|_| Error::PageFault)?;
+            tlb_flush(page);
+            return Ok(());
+        }
+
+        match (area.slots.get(&page), &area.backing) {
+            (Some(slot), _) => swap_area().unwrap().read_page(slot, &frame),
+            (None, Some(backing)) => {
+                let index = backing.page_index(area.start_va, page);
+                let cached = page_cache::get(&*backing.inode, index)?;
+                if !backing.shared && access.contains(PageTableFlags::Write) {
+                    frame.copy_from_frame(&cached);
+                } else {
+                    // a private page is only copied when written to
+                    let mut flags = area.flags;
+                    if !backing.shared {
+                        flags.set_writable(false);
+                    }
+                    self.pt
+                        .map(page, cached.start_phys_addr(), flags)
+                        .map_err(|_| Error::PageFault)?;
+                    area.mapper.insert(page, cached);
+                    return Ok(());
+                }
+            }
+            (None, None) => frame.writer().fill(0),
+        }
+        self.pt
+            .map(page, frame.start_phys_addr(), area.flags)
+            .map_err(|_| Error::PageFault)?;
+        area.mapper.insert(page, frame);
+        Ok(())
+    }
+
+    /// Pin the user page at `va` so the kernel can access it (`Read` or
+    /// `Write`), or `None` if it must be faulted in for that first. The kernel
+    /// may write to it, so its copy on the swap device is dropped, and for a
+    /// `Write` it is marked dirty as no hart does that for kernel writes.
+    pub fn pin_user_page(
+        &mut self,
+        va: VirtAddr,
+        access: PageTableFlags,
+    ) -> Result<Option<VirtMemFrame>, Error> {
+        let page = VirtAddr::from(va.floor());
+        let area = self
+            .areas
+            .range_mut(..=page)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| area.is_swappable() && page.0 < area.start_va.0 + area.size)
+            .ok_or(Error::PageFault)?;
+        let Some(frame) = area.mapper.get(&page) else {
+            return Ok(None);
+        };
+        if access.contains(PageTableFlags::Write) {
+            let mut flags = self
+                .pt
+                .translate(page)
+                .map_err(|_| Error::PageFault)?
+                .flags();
+            if !flags.is_writable() {
+                return Ok(None);
+            }
+            // so that `sync`, `unmap` and swapping write it back
+            self.pt
+                .update_flags(page, flags.set_dirty(true))
+                .map_err(|_| Error::PageFault)?;
+        }
+        area.slots.remove(&page);
+        Ok(Some(frame.clone()))
+    }
+
+    /// One step of the clock of [`swap::reclaim`](super::swap::reclaim): look
+    /// for a page to swap out among the resident user pages from `start` on,
+    /// giving those accessed since the last sweep a second chance. Returns the
+    /// page swapped out.
+    pub(crate) fn swap_out_one(&mut self, start: VirtAddr, swap: &SwapArea) -> Option<VirtAddr> {
+        for area in self.areas.values_mut() {
+            if !area.is_swappable() || area.start_va.0 + area.size <= start.0 {
+                continue;
+            }
+
+            let mut victim = None;
+            for (&va, frame) in area.mapper.range(start..) {
+                // a kernel buffer still points at it
+                if frame.is_shared() {
+                    continue;
+                }
+                let Ok(entry) = self.pt.translate(va) else {
+                    continue;
+                };
+                let mut flags = entry.flags();
+                if flags.is_accessed() {
+                    self.pt.update_flags(va, flags.set_accessed(false)).unwrap();
+                } else {
+                    victim = Some(va);
+                    break;
+                }
+            }
+            let Some(va) = victim else {
+                continue;
+            };
+
+            let new_slot = if area.slots.contains_key(&va) {
+                None
+            } else {
+                Some(swap.alloc_slot()?)
+            };
+            // no hart may write to the page while it is copied out
+            let entry = self.pt.unmap_deferred(va).unwrap();
+            self.pt.flush_tlb();
+            let frame = area.mapper.remove(&va).unwrap();
+            match new_slot {
+                Some(slot) => {
+                    swap.write_page(&slot, &frame);
+                    area.slots.insert(va, slot);
+                }
+                None if entry.flags().is_dirty() => swap.write_page(&area.slots[&va], &frame),
+                None => {}
+            }
+            return Some(va);
+        }
+        None
+    }
+
+    /// Map `area`, built over the frames of a shared memory segment, and keep
+    /// the segment alive for as long as it stays mapped.
+    pub(crate) fn map_shm(&mut self, area: MapArea, attachment: ShmAttachment) {
+        self.shm.insert(area.start_va, attachment);
+        self.map(area);
+    }
+
+    /// Whether the area starting at `start` maps a shared memory segment.
+    pub(crate) fn is_shm(&self, start: VirtAddr) -> bool {
+        self.shm.contains_key(&start)
+    }
+
+    /// Write the pages of shared file mappings in `start..end` that were
+    /// written to since they were read in, or since the last sync, back to
+    /// their files.
+    pub fn sync(&mut self, start: VirtAddr, end: VirtAddr) {
+        let mut dirty = Vec::new();
+        for area in self.areas.values() {
+            if !area.backing.as_ref().is_some_and(|backing| backing.shared) {
+                continue;
+            }
+            for &va in area.mapper.keys().filter(|&&va| start <= va && va < end) {
+                let mut flags = self.pt.translate(va).unwrap().flags();
+                if flags.is_dirty() {
+                    // later writes must mark it again
+                    self.pt.protect(va, flags.set_dirty(false)).unwrap();
+                    dirty.push((area.start_va, va));
+                }
+            }
+        }
+        self.pt.flush_tlb();
+        for (start, va) in dirty {
+            self.areas[&start].write_back(va);
+        }
+    }
+
+    /// The area starting exactly at `start`, if any.
+    pub fn area_at(&self, start: VirtAddr) -> Option<&MapArea> {
+        self.areas.get(&start)
+    }
+
     pub fn unmap(&mut self, va: VirtAddr) -> Result<(), crate::error::Error> {
         if let Some(area) = self.areas.remove(&va) {
+            let mut dirty = Vec::new();
             for (va, _) in area.mapper.iter() {
-                self.pt.unmap(*va).unwrap();
+                if self.pt.unmap_deferred(*va).unwrap().flags().is_dirty() {
+                    dirty.push(*va);
+                }
             }
+            // the frames of `area` must not be reused before every hart forgot them
+            self.pt.flush_tlb();
+            dirty.into_iter().for_each(|va| area.write_back(va));
+            self.shm.remove(&va);
             Ok(())
         } else {
             Err(Error::PageFault)
@@ -497,12 +808,20 @@ impl MemorySet {
     }
 
     pub fn clear(&mut self) {
+        let mut dirty = Vec::new();
         for area in self.areas.values_mut() {
             for (va, _) in area.mapper.iter() {
-                self.pt.unmap(*va).unwrap();
+                if self.pt.unmap_deferred(*va).unwrap().flags().is_dirty() {
+                    dirty.push((area.start_va, *va));
+                }
             }
         }
+        self.pt.flush_tlb();
+        for (start, va) in dirty {
+            self.areas[&start].write_back(va);
+        }
         self.areas.clear();
+        self.shm.clear();
     }
 
     pub fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), crate::error::Error> {
@@ -554,17 +873,48 @@ impl MemorySet {
         Err(Error::PageFault)
     }
 
+    /// The `satp` value of this address space, tagged with its ASID.
     pub fn token(&self) -> usize {
-        self.pt.get_root_paddr().0
+        make_satp(self.pt.asid.get(), self.pt.get_root_paddr().floor())
+    }
+
+    /// Get this hart ready to enter the address space and return the `satp`
+    /// value to load.
+    pub fn activate(&self) -> usize {
+        self.pt.asid.refresh();
+        asid::sync_hart();
+        // unmappers on other harts have to shoot this hart down from now on
+        self.pt.active_harts.enter();
+        self.token()
     }
 
+    /// This hart went back to the kernel space.
+    pub fn deactivate(&self) {
+        // without ASIDs, the trampoline flushed the translations of this space
+        if !asid::enabled() {
+            self.pt.active_harts.leave();
+        }
+    }
 }
 
 impl Clone for MemorySet {
     fn clone(&self) -> Self {
         let mut ms = Self::new();
         for area in self.areas.values() {
-            ms.map(area.clone());
+            match self.shm.get(&area.start_va) {
+                // the copy shares the segment rather than getting its own frames
+                Some(attachment) => ms.map_shm(
+                    MapArea::new_with_frames(
+                        area.start_va,
+                        area.size,
+                        area.flags,
+                        area.map_type,
+                        area.mapper.values().cloned().collect(),
+                    ),
+                    attachment.clone(),
+                ),
+                None => ms.map(area.clone()),
+            }
         }
         ms
     }
@@ -589,11 +939,19 @@ pub fn init() {
 
     info!("root_addr:0x{:x?}", addr.0);
 
+    table.active_harts.enter();
     mm_csr(addr);
+    asid::init(probe_asid_bits());
 
     println!("kernel space initialized");
 }
 
+pub fn activate_kernel_space() {
+    let kernel_space = KERNEL_SPACE.get().unwrap().lock();
+    kernel_space.pt.active_harts.enter();
+    mm_csr(kernel_space.pt.get_root_paddr());
+}
+
 #[allow(unused)]
 pub fn remap_test() {
     let mut kernel_space = KERNEL_SPACE.get().unwrap().lock();
@@ -1,22 +1,88 @@
-use crate::config::PAGE_SIZE;
+use core::fmt::{self, Display, Formatter};
+
+use log::info;
+
+use crate::{
+    arch::mm::probe_paging_levels,
+    config::{PAGE_SIZE, PAGE_SIZE_BITS},
+};
 
 pub(crate) mod address;
+pub(crate) mod asid;
 mod frame;
 mod frame_allocator;
 mod heap_allocator;
+pub(crate) mod memory_map;
 pub mod memory_set;
 pub mod option;
+pub(crate) mod page_cache;
 pub(crate) mod page_table;
+pub(crate) mod shm;
+mod slab;
+pub(crate) mod swap;
+pub(crate) mod tlb;
+
+pub(crate) use self::frame::VirtMemSegment;
 
 pub fn init() {
+    let levels = probe_paging_levels();
+    info!("[kernel] paging with Sv{}", PAGE_SIZE_BITS + 9 * levels);
     heap_allocator::init_heap();
+    memory_map::init();
     frame_allocator::init_frame_allocator();
+    swap::init();
     //heap_test();
     //frame_allocator_test();
     //option::frame_allocator_test();
+    //option::contiguous_alloc_test();
     memory_set::init();
     //memory_set::remap_test();
     //memory_set::write_test();
+    info!("[kernel] {}", stats());
+}
+
+/// Switch a secondary hart to the kernel space built by [`init`].
+pub fn init_secondary() {
+    memory_set::activate_kernel_space();
+}
+
+/// How much memory the kernel heap, its slab caches and the frame allocator use.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryStats {
+    pub heap: heap_allocator::HeapStats,
+    pub slabs: [slab::SlabStats; slab::CACHE_COUNT],
+    pub frames: frame_allocator::FrameStats,
+}
+
+impl Display for MemoryStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buddy heap: {}/{} KiB used ({} KiB requested), frames: {}/{} used ({} by the heap)",
+            self.heap.used / 1024,
+            self.heap.total / 1024,
+            self.heap.requested / 1024,
+            self.frames.used,
+            self.frames.total,
+            self.frames.heap,
+        )?;
+        for cache in self.slabs.iter().filter(|cache| cache.slabs > 0) {
+            write!(
+                f,
+                "\n  slab {}: {}/{} objects in {} pages, {} allocs, {} frees",
+                cache.size, cache.in_use, cache.objects, cache.slabs, cache.allocs, cache.frees,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn stats() -> MemoryStats {
+    MemoryStats {
+        heap: heap_allocator::stats(),
+        slabs: slab::stats(),
+        frames: frame_allocator::stats(),
+    }
 }
 
 pub const fn is_page_aligned(p: usize) -> bool {
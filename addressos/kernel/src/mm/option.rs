@@ -1,12 +1,18 @@
 use alloc::vec::Vec;
 
-use crate::error::Error;
+use crate::{config::PAGE_SIZE, error::Error};
 
-use super::{frame::VirtMemFrame, frame_allocator};
+use super::{
+    address::PhysPageNum,
+    frame::{VirtMemFrame, VirtMemSegment},
+    frame_allocator,
+};
 
 pub struct VirtMemAllocOption {
     frame_num: usize,
     is_uninit: bool,
+    is_contiguous: bool,
+    align: usize,
 }
 
 impl VirtMemAllocOption {
@@ -14,6 +20,8 @@ impl VirtMemAllocOption {
         Self {
             frame_num,
             is_uninit: false,
+            is_contiguous: false,
+            align: PAGE_SIZE,
         }
     }
 
@@ -22,7 +30,34 @@ impl VirtMemAllocOption {
         self
     }
 
+    /// Ask for physically contiguous frames, which devices doing DMA need.
+    pub fn set_contiguous(&mut self, contiguous: bool) -> &mut Self {
+        self.is_contiguous = contiguous;
+        self
+    }
+
+    /// Align the physical address of contiguous frames to `align` bytes, a
+    /// power of two no smaller than a page.
+    pub fn set_align(&mut self, align: usize) -> &mut Self {
+        self.align = align;
+        self
+    }
+
     pub fn alloc(&self) -> Result<Vec<VirtMemFrame>, Error> {
+        if self.is_contiguous {
+            // each frame goes back on its own when dropped
+            let start = self.alloc_contiguous_frames()?;
+            let frames: Vec<_> = (0..self.frame_num)
+                .map(|i| VirtMemFrame::new(start + i))
+                .collect();
+            if !self.is_uninit {
+                for frame in frames.iter() {
+                    frame.writer().fill(0);
+                }
+            }
+            return Ok(frames);
+        }
+
         let frames = {
             let mut frame_list = Vec::new();
             for _ in 0..self.frame_num {
@@ -39,6 +74,27 @@ impl VirtMemAllocOption {
         Ok(frames)
     }
 
+    /// Allocate contiguous frames as one segment, which `set_contiguous(true)`
+    /// must have been asked for.
+    pub fn alloc_contiguous(&self) -> Result<VirtMemSegment, Error> {
+        if !self.is_contiguous {
+            return Err(Error::InvalidArgs);
+        }
+        let segment = VirtMemSegment::new(self.alloc_contiguous_frames()?, self.frame_num);
+        if !self.is_uninit {
+            segment.writer().fill(0);
+        }
+        Ok(segment)
+    }
+
+    fn alloc_contiguous_frames(&self) -> Result<PhysPageNum, Error> {
+        if self.frame_num == 0 || !self.align.is_power_of_two() || self.align < PAGE_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        frame_allocator::alloc_contiguous(self.frame_num, self.align / PAGE_SIZE)
+            .ok_or(Error::NoMemory)
+    }
+
     pub fn alloc_single(&self) -> Result<VirtMemFrame, Error> {
         if self.frame_num != 1 {
             return Err(Error::InvalidArgs);
@@ -72,3 +128,32 @@ pub fn frame_allocator_test() {
     drop(v);
     println!("frame_allocator_test passed!");
 }
+
+#[allow(unused)]
+/// a simple test for contiguous allocation
+pub fn contiguous_alloc_test() {
+    let align = 16 * PAGE_SIZE;
+    let segment = VirtMemAllocOption::new(5)
+        .set_contiguous(true)
+        .set_align(align)
+        .alloc_contiguous()
+        .unwrap();
+    assert_eq!(segment.start_phys_addr().0 % align, 0);
+    assert_eq!(
+        segment.end_phys_addr().0 - segment.start_phys_addr().0,
+        5 * PAGE_SIZE
+    );
+    assert_eq!(segment.nframes(), 5);
+    assert!(segment.reader().has_remain());
+    drop(segment);
+
+    let frames = VirtMemAllocOption::new(3)
+        .set_contiguous(true)
+        .alloc()
+        .unwrap();
+    for pair in frames.windows(2) {
+        assert_eq!(pair[1].frame_index().0, pair[0].frame_index().0 + 1);
+    }
+    drop(frames);
+    println!("contiguous_alloc_test passed!");
+}
@@ -0,0 +1,104 @@
+//! Pages of files kept in memory.
+//!
+//! Every mapping of a file page shares the one frame cached for it, which is
+//! read in on the first fault. Frames only the cache still holds are clean,
+//! since shared mappings write their pages back when unmapped, so they can be
+//! dropped whenever memory runs out.
+
+use alloc::collections::BTreeMap;
+use spin::mutex::SpinMutex;
+
+use crate::{config::PAGE_SIZE, error::Error, fs::Inode};
+
+use super::{frame::VirtMemFrame, option::VirtMemAllocOption};
+
+/// Frames by inode id and page index in the file.
+static PAGE_CACHE: SpinMutex<BTreeMap<(usize, usize), VirtMemFrame>> =
+    SpinMutex::new(BTreeMap::new());
+
+/// The frame holding page `index` of `inode`. Fails past the end of the file.
+pub(crate) fn get(inode: &dyn Inode, index: usize) -> Result<VirtMemFrame, Error> {
+    let key = (inode.id(), index);
+    if let Some(frame) = PAGE_CACHE.lock().get(&key) {
+        return Ok(frame.clone());
+    }
+    let offset = index * PAGE_SIZE;
+    if offset >= inode.size() {
+        return Err(Error::PageFault);
+    }
+    // the part past the end of the file stays zeroed
+    let frame = VirtMemAllocOption::new(1).alloc_single()?;
+    let page = unsafe { core::slice::from_raw_parts_mut(frame.as_mut_ptr(), PAGE_SIZE) };
+    inode.read_at(offset, page);
+    // someone may have read it in meanwhile
+    Ok(PAGE_CACHE.lock().entry(key).or_insert(frame).clone())
+}
+
+/// The page `index` of `inode` if it is cached.
+fn cached(inode: &dyn Inode, index: usize) -> Option<VirtMemFrame> {
+    PAGE_CACHE.lock().get(&(inode.id(), index)).cloned()
+}
+
+/// Split the `len` bytes from `offset` into parts within one page each: their
+/// page index, offset in the page and offset from `offset`, and length.
+fn pages(offset: usize, len: usize) -> impl Iterator<Item = (usize, usize, usize, usize)> {
+    let mut done = 0;
+    core::iter::from_fn(move || {
+        if done == len {
+            return None;
+        }
+        let pos = offset + done;
+        let in_page = pos % PAGE_SIZE;
+        let part = (PAGE_SIZE - in_page).min(len - done);
+        done += part;
+        Some((pos / PAGE_SIZE, in_page, done - part, part))
+    })
+}
+
+/// Read from `offset` of `inode` into `buf` like [`Inode::read_at`], taking
+/// the pages that are cached from the cache, which mappings write to.
+pub(crate) fn read(inode: &dyn Inode, offset: usize, buf: &mut [u8]) -> usize {
+    let len = buf.len().min(inode.size().saturating_sub(offset));
+    for (index, in_page, done, part) in pages(offset, len) {
+        let dst = &mut buf[done..done + part];
+        match cached(inode, index) {
+            Some(frame) => {
+                let page = unsafe { core::slice::from_raw_parts(frame.as_ptr(), PAGE_SIZE) };
+                dst.copy_from_slice(&page[in_page..in_page + part]);
+            }
+            None => {
+                inode.read_at(offset + done, dst);
+            }
+        }
+    }
+    len
+}
+
+/// Write `buf` at `offset` of `inode` like [`Inode::write_at`], and to the
+/// pages of it that are cached, so that mappings see it.
+pub(crate) fn write(inode: &dyn Inode, offset: usize, buf: &[u8]) -> usize {
+    let written = inode.write_at(offset, buf);
+    for (index, in_page, done, part) in pages(offset, written) {
+        if let Some(frame) = cached(inode, index) {
+            let page = unsafe { core::slice::from_raw_parts_mut(frame.as_mut_ptr(), PAGE_SIZE) };
+            page[in_page..in_page + part].copy_from_slice(&buf[done..done + part]);
+        }
+    }
+    written
+}
+
+/// Write page `index` of `inode` back from `frame`, without growing the file.
+pub(crate) fn write_back(inode: &dyn Inode, index: usize, frame: &VirtMemFrame) {
+    let offset = index * PAGE_SIZE;
+    let len = inode.size().saturating_sub(offset).min(PAGE_SIZE);
+    let page = unsafe { core::slice::from_raw_parts(frame.as_ptr(), len) };
+    inode.write_at(offset, page);
+}
+
+/// Drop the pages nobody maps. Returns how many frames were freed.
+pub(crate) fn shrink() -> usize {
+    let mut cache = PAGE_CACHE.lock();
+    let cached = cache.len();
+    cache.retain(|_, frame| frame.is_shared());
+    cached - cache.len()
+}
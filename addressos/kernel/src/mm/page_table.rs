@@ -1,14 +1,24 @@
 use core::{fmt::Debug, marker::PhantomData, mem::size_of};
 
-use alloc::{borrow::ToOwned, vec::Vec};
+use alloc::{borrow::ToOwned, collections::BTreeMap, vec::Vec};
 use bytemuck::{Pod, Zeroable};
 use log::info;
 
-use crate::{arch::mm::tlb_flush, config::PAGE_SIZE, mm::option::VirtMemAllocOption};
+use crate::{
+    arch::{
+        config::MAX_PAGING_LEVELS,
+        mm::{paging_levels, tlb_flush, PageTableEntry},
+    },
+    config::PAGE_SIZE,
+    error::Error,
+    mm::option::VirtMemAllocOption,
+};
 
 use super::{
-    address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
+    address::{phys_to_virt, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
+    asid::Asid,
     frame::VirtMemFrame,
+    tlb::{ActiveHarts, TlbBatch},
 };
 
 pub(crate) trait PageTableFlagsTrait: Clone + Copy + Sized + Pod + Zeroable + Debug {
@@ -24,6 +34,10 @@ pub(crate) trait PageTableFlagsTrait: Clone + Copy + Sized + Pod + Zeroable + De
 
     fn set_executable(&mut self, executable: bool) -> Self;
 
+    fn set_accessed(&mut self, accessed: bool) -> Self;
+
+    fn set_dirty(&mut self, dirty: bool) -> Self;
+
     fn is_valid(&self) -> bool;
 
     fn is_writable(&self) -> bool;
@@ -37,6 +51,10 @@ pub(crate) trait PageTableFlagsTrait: Clone + Copy + Sized + Pod + Zeroable + De
     fn is_accessed(&self) -> bool;
 
     fn is_dirty(&self) -> bool;
+
+    /// Whether the entry maps memory instead of pointing at the next level
+    /// table. Above level 1 such an entry maps a huge page.
+    fn is_leaf(&self) -> bool;
 }
 
 pub(crate) trait PageTableEntryTrait: Clone + Copy + Sized + Pod + Zeroable + Debug {
@@ -55,6 +73,12 @@ pub(crate) trait PageTableEntryTrait: Clone + Copy + Sized + Pod + Zeroable + De
     fn clear(&mut self);
 }
 
+/// Size of the memory mapped by one leaf entry at `level`, level 1 holding
+/// the 4 KiB pages: 4 KiB, 2 MiB, 1 GiB and so on.
+pub(crate) const fn page_size_at(level: usize) -> usize {
+    PAGE_SIZE << (9 * (level - 1))
+}
+
 #[derive(Debug)]
 pub(crate) enum PageTableError {
     InvalidModification,
@@ -70,69 +94,107 @@ pub(crate) struct KernelMode;
 #[derive(Clone)]
 pub(crate) struct DeviceMode;
 
+/// An intermediate table of a [`PageTable`] and the number of its entries in use.
+#[derive(Debug)]
+struct TableFrame {
+    frame: VirtMemFrame,
+    used: usize,
+}
+
 #[derive(Debug)]
 pub(crate) struct PageTable<T: PageTableEntryTrait> {
     root_paddr: PhysAddr,
-    tables: Vec<VirtMemFrame>,
+    root_frame: VirtMemFrame,
+    /// Every table below the root, which is freed once its last entry is cleared.
+    tables: BTreeMap<PhysPageNum, TableFrame>,
+    /// Tables freed by unmapping, kept until the TLBs are flushed since harts
+    /// may still walk them.
+    freed_tables: Vec<VirtMemFrame>,
+    /// Harts that may have translations from this table in their TLB.
+    pub active_harts: ActiveHarts,
+    pub asid: Asid,
+    /// Unmapped pages not flushed from the TLBs yet.
+    tlb_batch: TlbBatch,
     phantom: PhantomData<T>,
 }
 
+/// The `index`-th entry of the table in the frame `table`.
+fn entry_at<T: PageTableEntryTrait>(table: PhysPageNum, index: usize) -> &'static mut T {
+    debug_assert!(size_of::<T>() * (index + 1) <= PAGE_SIZE);
+    let addr = phys_to_virt(usize::from(PhysAddr::from(table))) + size_of::<T>() * index;
+    unsafe { &mut *(addr as *mut T) }
+}
+
 impl<T: PageTableEntryTrait> PageTable<T> {
     pub fn new() -> Self {
         let root_frame = VirtMemAllocOption::new(1).alloc_single().unwrap();
 
         Self {
             root_paddr: root_frame.start_phys_addr(),
-            tables: vec![root_frame],
+            root_frame,
+            tables: BTreeMap::new(),
+            freed_tables: Vec::new(),
+            active_harts: ActiveHarts::new(),
+            asid: Asid::new(),
+            tlb_batch: TlbBatch::new(),
             phantom: PhantomData,
         }
     }
 
-    pub fn from_token(satp: usize) -> Self {
-        Self {
-            root_paddr: PhysAddr::from(satp * PAGE_SIZE),
-            tables: Vec::new(),
-            phantom: PhantomData,
-        }
+    /// A read-only view of the table.
+    pub fn view(&self) -> PageTableView<T> {
+        PageTableView::new(self.root_paddr)
     }
 
-    fn page_walk(&mut self, addr: VirtAddr, create: bool) -> Option<&mut T> {
-        let mut count = 3;
-
-        let mut current_entry = unsafe {
-            &mut *((usize::from(self.root_paddr) + size_of::<T>() * T::page_index(addr, count))
-                as *mut T)
+    /// Account for one more (`used`) or one less entry in use in `table`.
+    /// Returns whether `table` is now empty. The root is never counted.
+    fn count_entry(&mut self, table: PhysPageNum, used: bool) -> bool {
+        let Some(table) = self.tables.get_mut(&table) else {
+            return false;
         };
+        if used {
+            table.used += 1;
+        } else {
+            table.used -= 1;
+        }
+        table.used == 0
+    }
 
-        while count > 1 {
-            if !current_entry.flags().is_valid() {
-                if !create {
-                    return None;
-                }
+    /// Find where the entry for `addr` at `level` lives, creating the tables on
+    /// the way. The walk stops early at a huge page, so the level of the entry
+    /// found is returned with its table and index.
+    fn page_walk(&mut self, addr: VirtAddr, level: usize) -> (PhysPageNum, usize, usize) {
+        let mut table = self.root_paddr.floor();
+        let mut count = paging_levels();
+
+        loop {
+            let index = T::page_index(addr, count);
+            if count == level {
+                return (table, index, count);
+            }
+            let entry = entry_at::<T>(table, index);
 
+            if !entry.flags().is_valid() {
                 let frame = VirtMemAllocOption::new(1).alloc_single().unwrap();
 
                 let flags = T::F::new().set_valid(true);
 
-                current_entry.update(frame.start_phys_addr().into(), flags);
+                entry.update(frame.start_phys_addr().into(), flags);
 
-                self.tables.push(frame);
+                self.count_entry(table, true);
+                self.tables.insert(
+                    frame.start_phys_addr().floor(),
+                    TableFrame { frame, used: 0 },
+                );
             }
 
-            // if current_entry.flags().is_huge() {
-            //     break;
-            // }
+            if entry.flags().is_leaf() {
+                return (table, index, count);
+            }
 
+            table = entry.phys_page_num();
             count -= 1;
-            debug_assert!(size_of::<T>() * (T::page_index(addr, count) + 1) <= PAGE_SIZE);
-
-            current_entry = unsafe {
-                &mut *((usize::from(PhysAddr::from(current_entry.phys_page_num()))
-                    + size_of::<T>() * T::page_index(addr, count)) as *mut T)
-            };
         }
-
-        Some(current_entry)
     }
 
     pub fn map(
@@ -141,48 +203,269 @@ impl<T: PageTableEntryTrait> PageTable<T> {
         target: PhysAddr,
         flags: T::F,
     ) -> Result<(), PageTableError> {
-        let entry = self
-            .page_walk(addr, true)
-            .ok_or(PageTableError::InvalidVaddr)?;
+        self.map_huge(addr, target, flags, 1)
+    }
+
+    /// Map the [`page_size_at(level)`](page_size_at) bytes at `addr` with a
+    /// single leaf entry. Both addresses must be aligned to that size.
+    pub fn map_huge(
+        &mut self,
+        addr: VirtAddr,
+        target: PhysAddr,
+        flags: T::F,
+        level: usize,
+    ) -> Result<(), PageTableError> {
+        debug_assert!((1..=paging_levels()).contains(&level));
+        let size = page_size_at(level);
+        if usize::from(addr) % size != 0 || target.0 % size != 0 {
+            return Err(PageTableError::InvalidVaddr);
+        }
+
+        let (table, index, found) = self.page_walk(addr, level);
+        let entry = entry_at::<T>(table, index);
 
         //println!("{:?}", entry.flags());
 
-        if entry.is_used() && entry.flags().is_valid() {
+        // a huge page already covers `addr`, or smaller pages are mapped below
+        if found != level || (entry.is_used() && entry.flags().is_valid()) {
             return Err(PageTableError::InvalidModification);
         }
 
         entry.update(target.floor(), flags);
+        self.count_entry(table, true);
         tlb_flush(addr);
         Ok(())
     }
 
     pub fn unmap(&mut self, addr: VirtAddr) -> Result<(), PageTableError> {
-        let entry = self
-            .page_walk(addr, false)
-            .ok_or(PageTableError::InvalidVaddr)?;
+        self.unmap_deferred(addr)?;
+        self.flush_tlb();
+        Ok(())
+    }
 
-        if !entry.flags().is_valid() {
-            return Err(PageTableError::InvalidModification);
-        }
+    /// Like [`PageTable::unmap`], but leaves the stale translation in the TLBs
+    /// until the next [`PageTable::flush_tlb`].
+    ///
+    /// Tables left empty are released from the bottom up.
+    /// Returns the entry as it was, with the `Accessed` and `Dirty` bits.
+    pub fn unmap_deferred(&mut self, addr: VirtAddr) -> Result<T, PageTableError> {
+        // (table, index) of the entries walked through, from the root down
+        let mut path = [(PhysPageNum(0), 0); MAX_PAGING_LEVELS];
+        let mut depth = 0;
+        let mut table = self.root_paddr.floor();
+        let mut level = paging_levels();
+
+        let entry = loop {
+            let index = T::page_index(addr, level);
+            let entry = entry_at::<T>(table, index);
+            path[depth] = (table, index);
+            depth += 1;
+
+            if !entry.flags().is_valid() {
+                return Err(if level == 1 {
+                    PageTableError::InvalidModification
+                } else {
+                    PageTableError::InvalidVaddr
+                });
+            }
+            if level == 1 || entry.flags().is_leaf() {
+                break entry;
+            }
 
+            table = entry.phys_page_num();
+            level -= 1;
+        };
+
+        // unmapping a huge page drops all of it
+        let old = *entry;
         entry.clear();
-        tlb_flush(addr);
+        let size = page_size_at(level);
+        let start = VirtAddr::from(usize::from(addr) & !(size - 1));
+        self.tlb_batch.add_range(start, size);
+
+        for i in (1..depth).rev() {
+            let (table, _) = path[i];
+            if !self.count_entry(table, false) {
+                return Ok(old);
+            }
+            let TableFrame { frame, .. } = self.tables.remove(&table).unwrap();
+            self.freed_tables.push(frame);
+            let (parent, index) = path[i - 1];
+            entry_at::<T>(parent, index).clear();
+            // a hart may have cached the entry pointing at the freed table
+            self.tlb_batch.add_all();
+        }
+        // the root lost an entry, which is not counted
+        Ok(old)
+    }
+
+    /// Shoot the pending unmapped pages down on every hart using this table.
+    pub fn flush_tlb(&mut self) {
+        self.tlb_batch.flush(&self.active_harts, self.asid.get());
+        // no hart can walk them any more
+        self.freed_tables.clear();
+    }
+
+    /// Change the flags of the 4 KiB page mapped at `addr`. The TLBs are left
+    /// alone, which is enough for the `Accessed` and `Dirty` bits.
+    pub fn update_flags(&mut self, addr: VirtAddr, flags: T::F) -> Result<(), PageTableError> {
+        let entry = self.translate(addr)?;
+        let (table, index, level) = self.page_walk(addr, 1);
+        if level != 1 {
+            return Err(PageTableError::InvalidModification);
+        }
+        entry_at::<T>(table, index).update(entry.phys_page_num(), flags);
         Ok(())
     }
 
-    pub fn translate(&mut self, addr: VirtAddr) -> Result<T, PageTableError> {
-        let entry = self
-            .page_walk(addr, false)
-            .ok_or(PageTableError::InvalidVaddr)?;
+    /// Like [`PageTable::update_flags`], for changes no hart may miss, such as
+    /// taking a permission away. The stale translation stays in the TLBs until
+    /// the next [`PageTable::flush_tlb`].
+    pub fn protect(&mut self, addr: VirtAddr, flags: T::F) -> Result<(), PageTableError> {
+        self.update_flags(addr, flags)?;
+        self.tlb_batch.add(addr);
+        Ok(())
+    }
 
-        if !entry.flags().is_valid() {
+    /// Point the 4 KiB page mapped at `addr` to `target` instead. The old
+    /// translation stays in the TLBs until the next [`PageTable::flush_tlb`].
+    pub fn remap(
+        &mut self,
+        addr: VirtAddr,
+        target: PhysAddr,
+        flags: T::F,
+    ) -> Result<(), PageTableError> {
+        self.translate(addr)?;
+        let (table, index, level) = self.page_walk(addr, 1);
+        if level != 1 {
             return Err(PageTableError::InvalidModification);
         }
+        entry_at::<T>(table, index).update(target.floor(), flags);
+        self.tlb_batch.add(addr);
+        Ok(())
+    }
 
-        Ok(*entry)
+    /// The entry mapping `addr`. Inside a huge page, this is the entry of the
+    /// 4 KiB page containing `addr`, as if it had been mapped on its own.
+    pub fn translate(&self, addr: VirtAddr) -> Result<T, PageTableError> {
+        self.view().translate(addr)
     }
 
     pub fn get_root_paddr(&self) -> PhysAddr {
         self.root_paddr
     }
-}
\ No newline at end of file
+}
+
+/// A page table borrowed from its owner, such as the table of a `satp` value.
+///
+/// It can only be read, and frees nothing when dropped.
+pub(crate) struct PageTableView<T: PageTableEntryTrait> {
+    root_paddr: PhysAddr,
+    phantom: PhantomData<T>,
+}
+
+impl<T: PageTableEntryTrait> PageTableView<T> {
+    pub fn new(root_paddr: PhysAddr) -> Self {
+        Self {
+            root_paddr,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn from_token(satp: usize) -> Self {
+        Self::new(PhysAddr::from(PhysPageNum::from(satp)))
+    }
+
+    /// See [`PageTable::translate`].
+    pub fn translate(&self, addr: VirtAddr) -> Result<T, PageTableError> {
+        let mut table = self.root_paddr.floor();
+        let mut level = paging_levels();
+
+        loop {
+            let mut entry = *entry_at::<T>(table, T::page_index(addr, level));
+
+            if !entry.flags().is_valid() {
+                return Err(if level == 1 {
+                    PageTableError::InvalidModification
+                } else {
+                    PageTableError::InvalidVaddr
+                });
+            }
+
+            if level == 1 {
+                return Ok(entry);
+            }
+            if entry.flags().is_leaf() {
+                let offset = addr.floor().0 & (page_size_at(level) / PAGE_SIZE - 1);
+                let ppn = PhysPageNum(entry.phys_page_num().0 + offset);
+                entry.update(ppn, entry.flags());
+                return Ok(entry);
+            }
+
+            table = entry.phys_page_num();
+            level -= 1;
+        }
+    }
+}
+
+/// Translate a user buffer into the kernel slices backing it, one per page.
+pub(crate) fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Result<Vec<&'static mut [u8]>, Error> {
+    let page_table = PageTableView::<PageTableEntry>::from_token(token);
+    let mut start = ptr as usize;
+    let end = start + len;
+    let mut buffers = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let page_va = VirtAddr::from(start_va.floor());
+        let ppn = page_table
+            .translate(page_va)
+            .map_err(|_| Error::PageFault)?
+            .phys_page_num();
+        let page_end = (usize::from(page_va) + PAGE_SIZE).min(end);
+        buffers.push(
+            &mut ppn.get_bytes_array()[start_va.page_offset()..page_end - usize::from(page_va)],
+        );
+        start = page_end;
+    }
+    Ok(buffers)
+}
+
+/// Translate a user pointer into a kernel reference. `T` must not cross a page boundary.
+pub(crate) fn translated_refmut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, Error> {
+    let page_table = PageTableView::<PageTableEntry>::from_token(token);
+    let va = VirtAddr::from(ptr as usize);
+    let ppn = page_table
+        .translate(VirtAddr::from(va.floor()))
+        .map_err(|_| Error::PageFault)?
+        .phys_page_num();
+    let pa = usize::from(PhysAddr::from(ppn)) + va.page_offset();
+    Ok(unsafe { (phys_to_virt(pa) as *mut T).as_mut().unwrap() })
+}
+
+/// A user buffer translated into the kernel slices backing it.
+pub(crate) struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+    /// Frames behind `buffers`, which cannot be swapped out while we hold them.
+    _pins: Vec<VirtMemFrame>,
+}
+
+impl UserBuffer {
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self::with_pins(buffers, Vec::new())
+    }
+
+    pub fn with_pins(buffers: Vec<&'static mut [u8]>, pins: Vec<VirtMemFrame>) -> Self {
+        Self {
+            buffers,
+            _pins: pins,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.len()).sum()
+    }
+}
@@ -0,0 +1,104 @@
+//! Shared memory segments.
+//!
+//! A segment is a set of frames living in a global table under a handle. Every
+//! attachment maps the very same frames into some address space, so writes
+//! through one are seen through all the others. The segment goes away, and
+//! its frames with it, once it is destroyed and its last attachment dropped.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use spin::mutex::SpinMutex;
+
+use crate::config::PAGE_SIZE;
+
+use super::{frame::VirtMemFrame, option::VirtMemAllocOption};
+
+static SHM_TABLE: SpinMutex<ShmTable> = SpinMutex::new(ShmTable {
+    next_handle: 0,
+    segments: BTreeMap::new(),
+});
+
+struct ShmTable {
+    next_handle: usize,
+    segments: BTreeMap<usize, ShmSegment>,
+}
+
+struct ShmSegment {
+    frames: Vec<VirtMemFrame>,
+    attachments: usize,
+    /// No new attachments once set.
+    destroyed: bool,
+}
+
+/// One mapping of a segment, which keeps it alive until dropped.
+#[derive(Debug)]
+pub(crate) struct ShmAttachment(usize);
+
+impl Clone for ShmAttachment {
+    fn clone(&self) -> Self {
+        SHM_TABLE
+            .lock()
+            .segments
+            .get_mut(&self.0)
+            .unwrap()
+            .attachments += 1;
+        Self(self.0)
+    }
+}
+
+impl Drop for ShmAttachment {
+    fn drop(&mut self) {
+        let mut table = SHM_TABLE.lock();
+        let segment = table.segments.get_mut(&self.0).unwrap();
+        segment.attachments -= 1;
+        if segment.attachments == 0 && segment.destroyed {
+            table.segments.remove(&self.0);
+        }
+    }
+}
+
+/// Create a segment of `size` bytes, rounded up to pages, and return its handle.
+///
+/// It lives on until [`destroy`]ed, even with nothing attached to it.
+pub(crate) fn create(size: usize) -> Option<usize> {
+    let frames = VirtMemAllocOption::new(size.div_ceil(PAGE_SIZE))
+        .alloc()
+        .ok()?;
+    let mut table = SHM_TABLE.lock();
+    let handle = table.next_handle;
+    table.next_handle += 1;
+    table.segments.insert(
+        handle,
+        ShmSegment {
+            frames,
+            attachments: 0,
+            destroyed: false,
+        },
+    );
+    Some(handle)
+}
+
+/// Attach to the segment `handle`, returning its frames to be mapped.
+pub(crate) fn attach(handle: usize) -> Option<(Vec<VirtMemFrame>, ShmAttachment)> {
+    let mut table = SHM_TABLE.lock();
+    let segment = table
+        .segments
+        .get_mut(&handle)
+        .filter(|segment| !segment.destroyed)?;
+    segment.attachments += 1;
+    Some((segment.frames.clone(), ShmAttachment(handle)))
+}
+
+/// Forbid new attachments to the segment `handle`, which goes away with the
+/// current ones. Fails if there is no such segment.
+pub(crate) fn destroy(handle: usize) -> Option<()> {
+    let mut table = SHM_TABLE.lock();
+    let segment = table
+        .segments
+        .get_mut(&handle)
+        .filter(|segment| !segment.destroyed)?;
+    segment.destroyed = true;
+    if segment.attachments == 0 {
+        table.segments.remove(&handle);
+    }
+    Some(())
+}
@@ -0,0 +1,199 @@
+//! Caches of small kernel objects.
+//!
+//! Small allocations of the kernel heap come from slabs: pages cut into
+//! objects of one size class, handed out and taken back through a free list
+//! threaded through the free objects themselves. The pages are never given
+//! back.
+//!
+//! With the `slab-debug` feature, every object sits between red zones, checked
+//! when it is freed, and free objects are filled with poison, checked when
+//! they are handed out again.
+
+use core::{alloc::Layout, mem::size_of, ptr::null_mut, slice};
+
+use spin::mutex::SpinMutex;
+
+use crate::config::PAGE_SIZE;
+
+/// Bytes before and after each object.
+const RED_ZONE: usize = if cfg!(feature = "slab-debug") { 8 } else { 0 };
+const RED_ZONE_BYTE: u8 = 0xbb;
+const POISON_BYTE: u8 = 0x6b;
+
+/// One cache per power of two from 8 to 2048 bytes. Larger objects go to the
+/// buddy heap.
+pub(super) const CACHE_COUNT: usize = 9;
+
+static CACHES: [SlabCache; CACHE_COUNT] = [
+    SlabCache::new(8),
+    SlabCache::new(16),
+    SlabCache::new(32),
+    SlabCache::new(64),
+    SlabCache::new(128),
+    SlabCache::new(256),
+    SlabCache::new(512),
+    SlabCache::new(1024),
+    SlabCache::new(2048),
+];
+
+/// Objects of one size.
+pub(super) struct SlabCache {
+    size: usize,
+    inner: SpinMutex<SlabInner>,
+}
+
+struct SlabInner {
+    /// The first free object, whose first word points to the next one, or 0.
+    free: usize,
+    stats: SlabStats,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    /// Size of the objects.
+    pub size: usize,
+    /// Pages cut into objects.
+    pub slabs: usize,
+    pub objects: usize,
+    pub in_use: usize,
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+/// The cache serving `layout`, if it is small enough.
+pub(super) fn cache_for(layout: Layout) -> Option<&'static SlabCache> {
+    // red zones only keep objects aligned to their own size
+    if cfg!(feature = "slab-debug") && layout.align() > RED_ZONE {
+        return None;
+    }
+    let size = layout.size().max(layout.align()).max(8).next_power_of_two();
+    CACHES.get(size.trailing_zeros() as usize - 3)
+}
+
+pub(super) fn stats() -> [SlabStats; CACHE_COUNT] {
+    core::array::from_fn(|i| CACHES[i].inner.lock().stats)
+}
+
+impl SlabCache {
+    const fn new(size: usize) -> Self {
+        Self {
+            size,
+            inner: SpinMutex::new(SlabInner {
+                free: 0,
+                stats: SlabStats {
+                    size,
+                    slabs: 0,
+                    objects: 0,
+                    in_use: 0,
+                    allocs: 0,
+                    frees: 0,
+                },
+            }),
+        }
+    }
+
+    /// Distance between two objects of a slab.
+    const fn stride(&self) -> usize {
+        self.size + 2 * RED_ZONE
+    }
+
+    /// Take an object, cutting a page from `new_page` into more when the cache
+    /// is empty. Returns null if there is no page to be had.
+    pub(super) fn alloc(&self, new_page: impl Fn() -> *mut u8) -> *mut u8 {
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.free != 0 {
+                let object = inner.free;
+                inner.free = unsafe { *(object as *const usize) };
+                inner.stats.in_use += 1;
+                inner.stats.allocs += 1;
+                drop(inner);
+                self.check_poison(object);
+                return object as *mut u8;
+            }
+            // getting a page may allocate, even from this cache
+            drop(inner);
+
+            let page = new_page();
+            if page.is_null() {
+                return null_mut();
+            }
+            let count = PAGE_SIZE / self.stride();
+            let mut inner = self.inner.lock();
+            for i in (0..count).rev() {
+                let object = page as usize + i * self.stride() + RED_ZONE;
+                self.init_object(object);
+                unsafe { *(object as *mut usize) = inner.free };
+                inner.free = object;
+            }
+            inner.stats.slabs += 1;
+            inner.stats.objects += count;
+        }
+    }
+
+    pub(super) fn dealloc(&self, ptr: *mut u8) {
+        let object = ptr as usize;
+        self.check_red_zones(object);
+        self.poison(object);
+        let mut inner = self.inner.lock();
+        unsafe { *(object as *mut usize) = inner.free };
+        inner.free = object;
+        inner.stats.in_use -= 1;
+        inner.stats.frees += 1;
+    }
+
+    fn init_object(&self, object: usize) {
+        if !cfg!(feature = "slab-debug") {
+            return;
+        }
+        unsafe {
+            slice::from_raw_parts_mut((object - RED_ZONE) as *mut u8, RED_ZONE).fill(RED_ZONE_BYTE);
+            slice::from_raw_parts_mut((object + self.size) as *mut u8, RED_ZONE)
+                .fill(RED_ZONE_BYTE);
+        }
+        self.poison(object);
+    }
+
+    fn poison(&self, object: usize) {
+        if cfg!(feature = "slab-debug") {
+            unsafe { slice::from_raw_parts_mut(object as *mut u8, self.size).fill(POISON_BYTE) };
+        }
+    }
+
+    /// Catch writes to a free object. Its first word links the free list.
+    fn check_poison(&self, object: usize) {
+        if !cfg!(feature = "slab-debug") {
+            return;
+        }
+        let start = object + size_of::<usize>();
+        let bytes =
+            unsafe { slice::from_raw_parts(start as *const u8, self.size - size_of::<usize>()) };
+        if let Some(offset) = bytes.iter().position(|&byte| byte != POISON_BYTE) {
+            panic!(
+                "slab: {}-byte object at {:#x} written at offset {} after it was freed",
+                self.size,
+                object,
+                offset + size_of::<usize>()
+            );
+        }
+    }
+
+    /// Catch writes past either end of an object being freed.
+    fn check_red_zones(&self, object: usize) {
+        if !cfg!(feature = "slab-debug") {
+            return;
+        }
+        let before = unsafe { slice::from_raw_parts((object - RED_ZONE) as *const u8, RED_ZONE) };
+        let after = unsafe { slice::from_raw_parts((object + self.size) as *const u8, RED_ZONE) };
+        if before
+            .iter()
+            .chain(after)
+            .any(|&byte| byte != RED_ZONE_BYTE)
+        {
+            panic!(
+                "slab: red zone of the {}-byte object at {:#x} overwritten",
+                self.size, object
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Swapping user pages out when frames run out.
+//!
+//! A clock sweeps over the resident pages of the user areas of every process.
+//! A page accessed since the last sweep loses its `Accessed` bit and gets a
+//! second chance, otherwise it is written to the swap device, if dirty, and
+//! unmapped. Faulting on it later brings it back.
+
+use alloc::{sync::Arc, vec::Vec};
+use log::info;
+use spin::{mutex::SpinMutex, once::Once};
+
+use crate::{
+    config::PAGE_SIZE,
+    drivers::block::{BlockDevice, RamDisk, BLOCK_SIZE},
+    task::manager::all_processes,
+};
+
+use super::{address::VirtAddr, frame::VirtMemFrame, memory_map::memory_map};
+
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SIZE;
+
+static SWAP: Once<SwapArea> = Once::new();
+
+/// Where the clock stopped: a pid and the page after the last one swapped out.
+static CLOCK_HAND: SpinMutex<(usize, VirtAddr)> = SpinMutex::new((0, VirtAddr(0)));
+
+/// A swap device cut into page-sized slots.
+pub(crate) struct SwapArea {
+    device: Arc<dyn BlockDevice>,
+    free_slots: SpinMutex<Vec<usize>>,
+}
+
+/// A slot of the swap device holding a copy of a page, freed when dropped.
+#[derive(Debug)]
+pub(crate) struct SwapSlot(usize);
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP.get().unwrap().free_slots.lock().push(self.0);
+    }
+}
+
+impl SwapArea {
+    fn new(device: Arc<dyn BlockDevice>) -> Self {
+        let slots = device.num_blocks() / BLOCKS_PER_PAGE;
+        Self {
+            device,
+            free_slots: SpinMutex::new((0..slots).rev().collect()),
+        }
+    }
+
+    pub(crate) fn alloc_slot(&self) -> Option<SwapSlot> {
+        self.free_slots.lock().pop().map(SwapSlot)
+    }
+
+    pub(crate) fn write_page(&self, slot: &SwapSlot, frame: &VirtMemFrame) {
+        let page = unsafe { core::slice::from_raw_parts(frame.as_ptr(), PAGE_SIZE) };
+        for (i, block) in page.chunks(BLOCK_SIZE).enumerate() {
+            self.device.write_block(slot.0 * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+
+    pub(crate) fn read_page(&self, slot: &SwapSlot, frame: &VirtMemFrame) {
+        let page = unsafe { core::slice::from_raw_parts_mut(frame.as_mut_ptr(), PAGE_SIZE) };
+        for (i, block) in page.chunks_mut(BLOCK_SIZE).enumerate() {
+            self.device.read_block(slot.0 * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+}
+
+pub(crate) fn swap_area() -> Option<&'static SwapArea> {
+    SWAP.get()
+}
+
+/// Swap to the RAM disk the [`memory_map`] reserved, if any.
+pub fn init() {
+    let Some(range) = memory_map().swap.clone() else {
+        return;
+    };
+    let disk = unsafe { RamDisk::new(range.start, range.len()) };
+    SWAP.call_once(|| SwapArea::new(Arc::new(disk)));
+    info!(
+        "[kernel] swap: {} pages on a RAM disk",
+        range.len() / PAGE_SIZE
+    );
+}
+
+/// Free one frame by swapping a user page out. Fails when there is no swap,
+/// or when every candidate page is busy or pinned.
+///
+/// Processes locked by anyone, including the caller, are skipped.
+pub(crate) fn reclaim() -> bool {
+    let Some(swap) = swap_area() else {
+        return false;
+    };
+    let processes = all_processes();
+    if processes.is_empty() {
+        return false;
+    }
+
+    let (mut pid, mut start) = *CLOCK_HAND.lock();
+    // twice around the clock: the first round may only clear accessed bits
+    for _ in 0..=2 * processes.len() {
+        let process = match processes.iter().find(|process| process.getpid() >= pid) {
+            Some(process) => process,
+            None => {
+                start = VirtAddr(0);
+                &processes[0]
+            }
+        };
+        if process.getpid() != pid {
+            start = VirtAddr(0);
+        }
+        pid = process.getpid();
+
+        if let Some(mut process_inner) = process.try_inner_exclusive_access() {
+            if let Some(va) = process_inner.memory_set.swap_out_one(start, swap) {
+                *CLOCK_HAND.lock() = (pid, VirtAddr(va.0 + PAGE_SIZE));
+                return true;
+            }
+        }
+        pid += 1;
+    }
+    false
+}
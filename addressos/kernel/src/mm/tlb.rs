@@ -0,0 +1,114 @@
+//! Keeping the TLBs of every hart coherent with the page tables.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    arch::mm::{remote_tlb_flush, tlb_flush, tlb_flush_asid},
+    config::PAGE_SIZE,
+    cpu::hart_id,
+    mm::address::VirtAddr,
+};
+
+/// Above this many pages, flushing the whole address space is cheaper than page by page.
+const FLUSH_ALL_THRESHOLD: usize = 32;
+
+/// Harts that may hold translations of one address space.
+#[derive(Debug)]
+pub(crate) struct ActiveHarts(AtomicUsize);
+
+impl ActiveHarts {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    /// Record that this hart has loaded the address space.
+    pub(crate) fn enter(&self) {
+        self.0.fetch_or(1 << hart_id(), Ordering::AcqRel);
+    }
+
+    /// Record that this hart has dropped every translation of the address space.
+    pub(crate) fn leave(&self) {
+        self.0.fetch_and(!(1 << hart_id()), Ordering::AcqRel);
+    }
+
+    pub(crate) fn mask(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Pages whose translation went away and still has to be flushed.
+///
+/// Unmapping a whole area only costs one shootdown this way.
+#[derive(Debug)]
+pub(crate) struct TlbBatch {
+    start: usize,
+    end: usize,
+    /// Drop the whole address space, including the cached upper level entries.
+    all: bool,
+}
+
+impl TlbBatch {
+    pub(crate) const fn new() -> Self {
+        Self {
+            start: 0,
+            end: 0,
+            all: false,
+        }
+    }
+
+    pub(crate) fn add(&mut self, addr: VirtAddr) {
+        self.add_range(addr, PAGE_SIZE);
+    }
+
+    /// Batch every page of `[addr, addr + size)`, such as a whole huge page.
+    pub(crate) fn add_range(&mut self, addr: VirtAddr, size: usize) {
+        let page = usize::from(addr) & !(PAGE_SIZE - 1);
+        if self.start == self.end {
+            self.start = page;
+            self.end = page + size;
+        } else {
+            self.start = self.start.min(page);
+            self.end = self.end.max(page + size);
+        }
+    }
+
+    /// Flush everything, as needed after freeing a table: a `sfence.vma` of
+    /// one address need not drop the upper level entries cached for it.
+    pub(crate) fn add_all(&mut self) {
+        self.all = true;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.start == self.end && !self.all
+    }
+
+    /// Flush the batched range on this hart and on every other hart in `harts`.
+    ///
+    /// Large ranges only cost dropping `asid` on this hart. Other harts may
+    /// still run the address space under an ASID of an older generation, so
+    /// they flush the range whatever the ASID.
+    pub(crate) fn flush(&mut self, harts: &ActiveHarts, asid: usize) {
+        if self.is_empty() {
+            return;
+        }
+        let (start, size) = if self.all {
+            (0, usize::MAX)
+        } else {
+            (self.start, self.end - self.start)
+        };
+        *self = Self::new();
+
+        if size / PAGE_SIZE > FLUSH_ALL_THRESHOLD {
+            tlb_flush_asid(asid);
+        } else {
+            (start..start + size)
+                .step_by(PAGE_SIZE)
+                .for_each(|va| tlb_flush(VirtAddr::from(va)));
+        }
+
+        let remote = harts.mask() & !(1 << hart_id());
+        if remote != 0 {
+            remote_tlb_flush(remote, VirtAddr::from(start), size);
+        }
+    }
+}
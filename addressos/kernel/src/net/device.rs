@@ -0,0 +1,62 @@
+//! The virtio network device as smoltcp sees it.
+
+use alloc::{vec, vec::Vec};
+
+use smoltcp::{
+    phy::{self, Device, DeviceCapabilities, Medium},
+    time::Instant,
+};
+
+use crate::drivers::VirtioNet;
+
+/// An Ethernet frame with its header, without the FCS.
+const MAX_FRAME_SIZE: usize = 1514;
+
+pub(super) struct RxToken(Vec<u8>);
+
+pub(super) struct TxToken<'a>(&'a mut VirtioNet);
+
+impl Device for VirtioNet {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken<'a>;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = MAX_FRAME_SIZE;
+        caps
+    }
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(RxToken, TxToken<'_>)> {
+        let packet = self.receive_packet()?;
+        Some((RxToken(packet), TxToken(self)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<TxToken<'_>> {
+        self.can_send().then_some(TxToken(self))
+    }
+}
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        if self.0.can_send() {
+            self.0.send_packet(len, f)
+        } else {
+            // a reply to a packet just received, with every buffer in flight:
+            // drop it, as a full wire would
+            f(&mut vec![0; len])
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Internet sockets: a TCP connection or listener, or a UDP endpoint.
+//!
+//! A socket owns handles to smoltcp sockets, one per link for those bound to
+//! every address. Calls that cannot go on yet sleep on the stack's wait queue
+//! and look again after each poll that moved something.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use smoltcp::{
+    iface::SocketHandle,
+    socket::{tcp, udp},
+    wire::{IpEndpoint, IpListenEndpoint, Ipv4Address},
+};
+use spin::mutex::SpinMutex;
+
+use crate::{error::Error, fs::File, mm::page_table::UserBuffer, task::block_current_and_run_next};
+
+use super::{net, scatter, LinkId, Net, Socket, SocketAddr, SocketType};
+
+const TCP_BUFFER_SIZE: usize = 16 * 1024;
+const UDP_BUFFER_SIZE: usize = 16 * 1024;
+const UDP_PACKETS: usize = 16;
+/// Most connections a listener holds before they are accepted.
+const MAX_BACKLOG: usize = 8;
+
+pub(crate) struct InetSocket {
+    ty: SocketType,
+    state: SpinMutex<State>,
+}
+
+enum State {
+    /// Not connected or listening yet, maybe bound to `local`.
+    Idle { local: Option<IpListenEndpoint> },
+    /// A TCP listener, with `backlog` smoltcp sockets listening on each link.
+    Listening {
+        local: IpListenEndpoint,
+        handles: Vec<(LinkId, SocketHandle)>,
+    },
+    /// A TCP connection, or an attempt at one.
+    Connected(LinkId, SocketHandle),
+    /// A bound UDP socket, sending to `remote` unless told otherwise.
+    Bound {
+        handles: Vec<(LinkId, SocketHandle)>,
+        remote: Option<IpEndpoint>,
+    },
+}
+
+/// Run `f` on the stack until it has an answer, sleeping while it has none.
+fn wait_for<T>(mut f: impl FnMut(&mut Net) -> Option<T>) -> T {
+    loop {
+        let mut net = net().lock();
+        if let Some(value) = f(&mut net) {
+            // send whatever `f` queued
+            net.poll();
+            return value;
+        }
+        net.waiters.push_current();
+        drop(net);
+        block_current_and_run_next();
+    }
+}
+
+fn tcp_socket() -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+    )
+}
+
+fn tcp_listener(
+    net: &mut Net,
+    link: LinkId,
+    local: IpListenEndpoint,
+) -> Result<SocketHandle, Error> {
+    let mut socket = tcp_socket();
+    socket.listen(local).map_err(|_| Error::InvalidArgs)?;
+    Ok(net.sockets(link).add(socket))
+}
+
+/// UDP sockets on each link `local` is on.
+fn udp_bind(net: &mut Net, local: IpListenEndpoint) -> Result<State, Error> {
+    let mut handles = Vec::new();
+    for link in net.links_for(local.addr) {
+        let mut socket = udp::Socket::new(
+            udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKETS],
+                vec![0; UDP_BUFFER_SIZE],
+            ),
+            udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKETS],
+                vec![0; UDP_BUFFER_SIZE],
+            ),
+        );
+        socket.bind(local).map_err(|_| Error::InvalidArgs)?;
+        handles.push((link, net.sockets(link).add(socket)));
+    }
+    Ok(State::Bound {
+        handles,
+        remote: None,
+    })
+}
+
+/// What an accepting listener found in one of its sockets.
+enum Pending {
+    None,
+    /// Reset before it was accepted, to listen again.
+    Reset,
+    Established(IpEndpoint),
+}
+
+impl InetSocket {
+    pub(crate) fn new(ty: SocketType) -> Self {
+        Self {
+            ty,
+            state: SpinMutex::new(State::Idle { local: None }),
+        }
+    }
+
+    fn send_stream(&self, buf: UserBuffer) -> Result<usize, Error> {
+        let mut sent = 0;
+        for buffer in buf.buffers.iter() {
+            let mut offset = 0;
+            while offset < buffer.len() {
+                let result = wait_for(|net| {
+                    let State::Connected(link, handle) = *self.state.lock() else {
+                        return Some(Err(Error::InvalidArgs));
+                    };
+                    let socket = net.sockets(link).get_mut::<tcp::Socket>(handle);
+                    if !socket.may_send() {
+                        Some(Err(Error::IoError))
+                    } else if socket.can_send() {
+                        Some(
+                            socket
+                                .send_slice(&buffer[offset..])
+                                .map_err(|_| Error::IoError),
+                        )
+                    } else {
+                        None
+                    }
+                });
+                match result {
+                    Ok(len) => offset += len,
+                    // the peer went away midway
+                    Err(_) if sent + offset > 0 => return Ok(sent + offset),
+                    Err(error) => return Err(error),
+                }
+            }
+            sent += offset;
+        }
+        Ok(sent)
+    }
+
+    fn send_datagram(&self, buf: UserBuffer, to: Option<IpEndpoint>) -> Result<usize, Error> {
+        // no bigger datagram fits in the send buffer anyway
+        if buf.len() > UDP_BUFFER_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        let data: Vec<u8> = buf.buffers.concat();
+        let unbound = matches!(*self.state.lock(), State::Idle { local: None });
+        if unbound {
+            self.bind(SocketAddr::Inet(unspecified()))?;
+        }
+        wait_for(|net| {
+            let state = self.state.lock();
+            let State::Bound { handles, remote } = &*state else {
+                return Some(Err(Error::InvalidArgs));
+            };
+            let Some(remote) = to.or(*remote) else {
+                return Some(Err(Error::InvalidArgs));
+            };
+            let link = net.route(remote.addr);
+            let Some(&(_, handle)) = handles.iter().find(|(on, _)| *on == link) else {
+                return Some(Err(Error::InvalidArgs));
+            };
+            let socket = net.sockets(link).get_mut::<udp::Socket>(handle);
+            if !socket.can_send() {
+                return None;
+            }
+            Some(
+                socket
+                    .send_slice(&data, remote)
+                    .map(|()| data.len())
+                    .map_err(|_| Error::IoError),
+            )
+        })
+    }
+}
+
+impl Socket for InetSocket {
+    /// Binds on every address if `addr` is unspecified, and on a port of our
+    /// choosing if its port is 0.
+    fn bind(&self, addr: SocketAddr) -> Result<(), Error> {
+        let local = inet(addr)?;
+        let mut net = net().lock();
+        let mut state = self.state.lock();
+        let State::Idle { local: None } = *state else {
+            return Err(Error::InvalidArgs);
+        };
+        let port = match local.port {
+            0 => net.ephemeral_port(),
+            port => port,
+        };
+        let local = IpListenEndpoint {
+            addr: (!local.addr.is_unspecified()).then_some(local.addr),
+            port,
+        };
+        // an address none of our links has
+        if net.links_for(local.addr).is_empty() {
+            return Err(Error::InvalidArgs);
+        }
+        *state = match self.ty {
+            SocketType::Stream => State::Idle { local: Some(local) },
+            SocketType::Datagram => udp_bind(&mut net, local)?,
+        };
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> Result<(), Error> {
+        let mut net = net().lock();
+        let mut state = self.state.lock();
+        let (SocketType::Stream, State::Idle { local }) = (self.ty, &*state) else {
+            return Err(Error::InvalidArgs);
+        };
+        let local = match *local {
+            Some(local) => local,
+            None => IpListenEndpoint::from(net.ephemeral_port()),
+        };
+        let mut handles = Vec::new();
+        for link in net.links_for(local.addr) {
+            for _ in 0..backlog.clamp(1, MAX_BACKLOG) {
+                handles.push((link, tcp_listener(&mut net, link, local)?));
+            }
+        }
+        *state = State::Listening { local, handles };
+        Ok(())
+    }
+
+    fn accept(&self) -> Result<(Arc<dyn File>, SocketAddr), Error> {
+        wait_for(|net| {
+            let mut state = self.state.lock();
+            let State::Listening { local, handles } = &mut *state else {
+                return Some(Err(Error::InvalidArgs));
+            };
+            for slot in handles.iter_mut() {
+                let (link, handle) = *slot;
+                let socket = net.sockets(link).get_mut::<tcp::Socket>(handle);
+                let pending = match socket.state() {
+                    tcp::State::Listen | tcp::State::SynReceived => Pending::None,
+                    tcp::State::Closed => Pending::Reset,
+                    _ => socket
+                        .remote_endpoint()
+                        .map_or(Pending::None, Pending::Established),
+                };
+                match pending {
+                    Pending::None => {}
+                    Pending::Reset => {
+                        let _ = socket.listen(*local);
+                    }
+                    Pending::Established(remote) => {
+                        // a fresh listener takes its place
+                        slot.1 = match tcp_listener(net, link, *local) {
+                            Ok(listener) => listener,
+                            Err(error) => return Some(Err(error)),
+                        };
+                        let connection = InetSocket {
+                            ty: SocketType::Stream,
+                            state: SpinMutex::new(State::Connected(link, handle)),
+                        };
+                        return Some(Ok((
+                            Arc::new(connection) as Arc<dyn File>,
+                            SocketAddr::Inet(remote),
+                        )));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Waits for the handshake of a TCP socket. A UDP socket only takes
+    /// `addr` as where to send.
+    fn connect(&self, addr: SocketAddr) -> Result<(), Error> {
+        let remote = inet(addr)?;
+        let mut net = net().lock();
+        let mut state = self.state.lock();
+        match (self.ty, &mut *state) {
+            (SocketType::Stream, State::Idle { local }) => {
+                let link = net.route(remote.addr);
+                let local = match *local {
+                    Some(local) => local,
+                    None => IpListenEndpoint::from(net.ephemeral_port()),
+                };
+                let mut socket = tcp_socket();
+                let (iface, sockets) = net.link(link);
+                socket
+                    .connect(iface.context(), remote, local)
+                    .map_err(|_| Error::InvalidArgs)?;
+                *state = State::Connected(link, sockets.add(socket));
+            }
+            (SocketType::Datagram, State::Idle { local: None }) => {
+                let local = IpListenEndpoint::from(net.ephemeral_port());
+                *state = udp_bind(&mut net, local)?;
+                let State::Bound { remote: to, .. } = &mut *state else {
+                    unreachable!();
+                };
+                *to = Some(remote);
+                return Ok(());
+            }
+            (SocketType::Datagram, State::Bound { remote: to, .. }) => {
+                *to = Some(remote);
+                return Ok(());
+            }
+            _ => return Err(Error::InvalidArgs),
+        }
+        drop(state);
+        net.poll();
+        drop(net);
+
+        wait_for(|net| {
+            let State::Connected(link, handle) = *self.state.lock() else {
+                return Some(Err(Error::InvalidArgs));
+            };
+            match net.sockets(link).get::<tcp::Socket>(handle).state() {
+                tcp::State::SynSent | tcp::State::SynReceived => None,
+                tcp::State::Established => Some(Ok(())),
+                // refused, or timed out
+                _ => Some(Err(Error::IoError)),
+            }
+        })
+    }
+
+    fn send(&self, buf: UserBuffer, to: Option<SocketAddr>) -> Result<usize, Error> {
+        let to = to.map(inet).transpose()?;
+        match self.ty {
+            SocketType::Stream => self.send_stream(buf),
+            SocketType::Datagram => self.send_datagram(buf, to),
+        }
+    }
+
+    fn recv(&self, mut buf: UserBuffer) -> Result<(usize, SocketAddr), Error> {
+        let (len, from) = wait_for(|net| {
+            let state = self.state.lock();
+            match &*state {
+                State::Connected(link, handle) => {
+                    let socket = net.sockets(*link).get_mut::<tcp::Socket>(*handle);
+                    let remote = socket.remote_endpoint().unwrap_or_else(unspecified);
+                    if !socket.can_recv() {
+                        // nothing more comes after the peer's FIN
+                        return (!socket.may_recv()).then_some(Ok((0, remote)));
+                    }
+                    let mut received = 0;
+                    for buffer in buf.buffers.iter_mut() {
+                        match socket.recv_slice(buffer) {
+                            Ok(len) => received += len,
+                            Err(_) => return Some(Err(Error::IoError)),
+                        }
+                        if !socket.can_recv() {
+                            break;
+                        }
+                    }
+                    Some(Ok((received, remote)))
+                }
+                State::Bound { handles, .. } => {
+                    for &(link, handle) in handles {
+                        let socket = net.sockets(link).get_mut::<udp::Socket>(handle);
+                        if !socket.can_recv() {
+                            continue;
+                        }
+                        // what does not fit in `buf` is dropped with the datagram
+                        return Some(
+                            socket
+                                .recv()
+                                .map(|(data, meta)| {
+                                    scatter(&mut buf, data);
+                                    (data.len().min(buf.len()), meta.endpoint)
+                                })
+                                .map_err(|_| Error::IoError),
+                        );
+                    }
+                    None
+                }
+                _ => Some(Err(Error::InvalidArgs)),
+            }
+        })?;
+        Ok((len, SocketAddr::Inet(from)))
+    }
+}
+
+/// The Internet address in `addr`.
+fn inet(addr: SocketAddr) -> Result<IpEndpoint, Error> {
+    match addr {
+        SocketAddr::Inet(endpoint) => Ok(endpoint),
+        SocketAddr::Unix(_) => Err(Error::InvalidArgs),
+    }
+}
+
+fn unspecified() -> IpEndpoint {
+    IpEndpoint::from((Ipv4Address::UNSPECIFIED, 0))
+}
+
+impl File for InetSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv(buf).map_or(0, |(len, _)| len)
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.send(buf, None).unwrap_or(0)
+    }
+
+    fn socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
+
+impl Drop for InetSocket {
+    fn drop(&mut self) {
+        let mut net = net().lock();
+        match core::mem::replace(self.state.get_mut(), State::Idle { local: None }) {
+            State::Idle { .. } => return,
+            State::Connected(link, handle) => {
+                net.sockets(link).get_mut::<tcp::Socket>(handle).close();
+                net.closing.push((link, handle));
+            }
+            State::Listening { handles, .. } => {
+                // connections never accepted are reset
+                for (link, handle) in handles {
+                    net.sockets(link).get_mut::<tcp::Socket>(handle).abort();
+                    net.closing.push((link, handle));
+                }
+            }
+            State::Bound { handles, .. } => {
+                for (link, handle) in handles {
+                    net.sockets(link).remove(handle);
+                }
+            }
+        }
+        net.poll();
+    }
+}
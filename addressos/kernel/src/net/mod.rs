@@ -0,0 +1,314 @@
+//! Sockets, and the network stack behind Internet ones: smoltcp over a
+//! loopback link and, once it is probed, the virtio network device. Unix
+//! sockets stay in the kernel, without any stack.
+//!
+//! One lock covers both links and every socket on them. Whoever changes a
+//! socket polls the stack right away, packets that arrive are polled on
+//! interrupt, and the timers of smoltcp run on a kernel timer. Tasks waiting
+//! on any socket sleep in one queue, all woken whenever a poll moves
+//! something.
+
+mod device;
+mod inet;
+mod unix;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use log::info;
+use smoltcp::{
+    iface::{Config, Interface, SocketHandle, SocketSet},
+    phy::{Device, Loopback, Medium},
+    socket::tcp,
+    time::Instant,
+    wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address},
+};
+use spin::{mutex::SpinMutex, Once};
+
+use crate::{
+    drivers::VirtioNet,
+    error::Error,
+    fs::File,
+    mm::page_table::UserBuffer,
+    sync::WaitQueue,
+    timer::{add_timer, get_time_ns},
+};
+
+pub(crate) use self::{inet::InetSocket, unix::UnixSocket};
+
+/// The address QEMU user networking hands out, and its gateway.
+const ETHERNET_ADDR: Ipv4Address = Ipv4Address::new(10, 0, 2, 15);
+const ETHERNET_PREFIX: u8 = 24;
+const GATEWAY: Ipv4Address = Ipv4Address::new(10, 0, 2, 2);
+
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+/// Polls asked for sooner than this are put off, so a timer never comes due
+/// while the timers are being run.
+const MIN_POLL_DELAY_NS: u64 = 1_000_000;
+
+static NET: Once<SpinMutex<Net>> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SocketType {
+    Stream,
+    Datagram,
+}
+
+/// Where a socket is, in one of the address families.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SocketAddr {
+    Inet(IpEndpoint),
+    /// A name in the kernel's namespace of Unix sockets, empty for a socket
+    /// without one.
+    Unix(Vec<u8>),
+}
+
+/// The calls a socket of any family answers, besides reading and writing it
+/// as a [`File`].
+pub(crate) trait Socket: Send + Sync {
+    /// Give the socket the local address `addr`.
+    fn bind(&self, addr: SocketAddr) -> Result<(), Error>;
+
+    /// Take up to `backlog` connections at a time on a bound stream socket.
+    fn listen(&self, backlog: usize) -> Result<(), Error>;
+
+    /// Wait for a connection, and return a socket for it with its peer.
+    fn accept(&self) -> Result<(Arc<dyn File>, SocketAddr), Error>;
+
+    /// Connect a stream socket to `addr`, or make it where a datagram socket
+    /// sends by default.
+    fn connect(&self, addr: SocketAddr) -> Result<(), Error>;
+
+    /// Send `buf` on the connection, or as one datagram to `to` or the
+    /// connected peer. Returns how many bytes were sent.
+    fn send(&self, buf: UserBuffer, to: Option<SocketAddr>) -> Result<usize, Error>;
+
+    /// Receive into `buf` what the connection has, or one datagram. Returns
+    /// how many bytes were received, 0 at the end of a connection, and from
+    /// whom.
+    fn recv(&self, buf: UserBuffer) -> Result<(usize, SocketAddr), Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkId {
+    Loopback,
+    Ethernet,
+}
+
+/// A device with the interface on it and the sockets bound to it.
+struct Link<D: Device> {
+    device: D,
+    iface: Interface,
+    sockets: SocketSet<'static>,
+}
+
+impl<D: Device> Link<D> {
+    fn new(
+        mut device: D,
+        hardware_addr: HardwareAddress,
+        cidr: IpCidr,
+        gateway: Option<Ipv4Address>,
+    ) -> Self {
+        let mut iface = Interface::new(Config::new(hardware_addr), &mut device, now());
+        iface.update_ip_addrs(|addrs| addrs.push(cidr).unwrap());
+        if let Some(gateway) = gateway {
+            iface.routes_mut().add_default_ipv4_route(gateway).unwrap();
+        }
+        Self {
+            device,
+            iface,
+            sockets: SocketSet::new(Vec::new()),
+        }
+    }
+
+    fn poll(&mut self) -> bool {
+        self.iface.poll(now(), &mut self.device, &mut self.sockets)
+    }
+
+    fn poll_delay(&mut self) -> Option<u64> {
+        self.iface
+            .poll_delay(now(), &self.sockets)
+            .map(|delay| delay.total_micros() * 1000)
+    }
+}
+
+struct Net {
+    lo: Link<Loopback>,
+    eth: Option<Link<VirtioNet>>,
+    /// Tasks waiting for some socket to change.
+    waiters: WaitQueue,
+    next_port: u16,
+    /// TCP sockets closed by their owner, still saying goodbye to the peer.
+    closing: Vec<(LinkId, SocketHandle)>,
+    /// When the armed timer polls, if one is armed.
+    poll_at: Option<u64>,
+}
+
+fn now() -> Instant {
+    Instant::from_micros((get_time_ns() / 1000) as i64)
+}
+
+/// Copy `data` to the front of `buf`.
+fn scatter(buf: &mut UserBuffer, mut data: &[u8]) {
+    for buffer in buf.buffers.iter_mut() {
+        let len = buffer.len().min(data.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+}
+
+fn net() -> &'static SpinMutex<Net> {
+    NET.get().expect("network stack not initialized")
+}
+
+/// Bring up the loopback link. The Ethernet link comes with its device.
+pub fn init() {
+    NET.call_once(|| {
+        let lo = Link::new(
+            Loopback::new(Medium::Ip),
+            HardwareAddress::Ip,
+            IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8),
+            None,
+        );
+        SpinMutex::new(Net {
+            lo,
+            eth: None,
+            waiters: WaitQueue::new(),
+            next_port: *EPHEMERAL_PORTS.start(),
+            closing: Vec::new(),
+            poll_at: None,
+        })
+    });
+}
+
+/// Bring up the Ethernet link on `device`.
+pub(crate) fn add_ethernet(device: VirtioNet) {
+    let mac = EthernetAddress(device.mac_address());
+    let cidr = IpCidr::new(IpAddress::Ipv4(ETHERNET_ADDR), ETHERNET_PREFIX);
+    let link = Link::new(device, HardwareAddress::Ethernet(mac), cidr, Some(GATEWAY));
+    info!("[kernel] eth0: {} at {}", mac, cidr);
+    let mut net = net().lock();
+    net.eth = Some(link);
+    net.poll();
+}
+
+/// Serve an interrupt of the network device.
+pub(crate) fn handle_irq() {
+    let mut net = net().lock();
+    if let Some(eth) = &net.eth {
+        eth.device.ack_interrupt();
+    }
+    net.poll();
+}
+
+/// Whether some task waits on a socket that packets from outside may wake.
+pub(crate) fn waiting_for_packets() -> bool {
+    NET.get().is_some_and(|net| {
+        let net = net.lock();
+        net.eth.is_some() && !net.waiters.is_empty()
+    })
+}
+
+impl Net {
+    /// Move packets in and out of every link, and wake the waiters if
+    /// anything moved.
+    fn poll(&mut self) {
+        let mut changed = self.lo.poll();
+        if let Some(eth) = &mut self.eth {
+            changed |= eth.poll();
+        }
+        self.reap_closing();
+        if changed {
+            self.waiters.wake_all();
+        }
+        self.arm_timer();
+    }
+
+    /// Drop the closed sockets that are done.
+    fn reap_closing(&mut self) {
+        let closing = core::mem::take(&mut self.closing);
+        for (link, handle) in closing {
+            let sockets = self.sockets(link);
+            let state = sockets.get::<tcp::Socket>(handle).state();
+            if matches!(state, tcp::State::Closed | tcp::State::TimeWait) {
+                sockets.remove(handle);
+            } else {
+                self.closing.push((link, handle));
+            }
+        }
+    }
+
+    /// Set a timer for when smoltcp next wants to be polled, unless one
+    /// fires before that already.
+    fn arm_timer(&mut self) {
+        let delay = self.lo.poll_delay();
+        let delay = match self.eth.as_mut().and_then(|eth| eth.poll_delay()) {
+            Some(eth_delay) => Some(delay.map_or(eth_delay, |delay| delay.min(eth_delay))),
+            None => delay,
+        };
+        let Some(delay) = delay.map(|delay| delay.max(MIN_POLL_DELAY_NS)) else {
+            return;
+        };
+        let at = get_time_ns() + delay;
+        if self.poll_at.is_some_and(|poll_at| poll_at <= at) {
+            return;
+        }
+        self.poll_at = Some(at);
+        add_timer(delay, move || {
+            let mut net = net().lock();
+            // a later timer may have been armed in between
+            if net.poll_at == Some(at) {
+                net.poll_at = None;
+            }
+            net.poll();
+        });
+    }
+
+    fn link(&mut self, link: LinkId) -> (&mut Interface, &mut SocketSet<'static>) {
+        match link {
+            LinkId::Loopback => (&mut self.lo.iface, &mut self.lo.sockets),
+            LinkId::Ethernet => {
+                let eth = self.eth.as_mut().expect("no Ethernet link");
+                (&mut eth.iface, &mut eth.sockets)
+            }
+        }
+    }
+
+    fn sockets(&mut self, link: LinkId) -> &mut SocketSet<'static> {
+        self.link(link).1
+    }
+
+    /// The link packets to `addr` leave on.
+    fn route(&self, addr: IpAddress) -> LinkId {
+        let IpAddress::Ipv4(addr) = addr;
+        if addr.is_loopback() || self.eth.is_none() {
+            LinkId::Loopback
+        } else {
+            LinkId::Ethernet
+        }
+    }
+
+    /// The links a socket bound to `addr`, or to every address, listens on.
+    /// None has an address no link has.
+    fn links_for(&self, addr: Option<IpAddress>) -> Vec<LinkId> {
+        match addr {
+            Some(addr) if self.lo.iface.has_ip_addr(addr) => alloc::vec![LinkId::Loopback],
+            Some(addr) => match &self.eth {
+                Some(eth) if eth.iface.has_ip_addr(addr) => alloc::vec![LinkId::Ethernet],
+                _ => Vec::new(),
+            },
+            None if self.eth.is_some() => alloc::vec![LinkId::Loopback, LinkId::Ethernet],
+            None => alloc::vec![LinkId::Loopback],
+        }
+    }
+
+    fn ephemeral_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = if port == *EPHEMERAL_PORTS.end() {
+            *EPHEMERAL_PORTS.start()
+        } else {
+            port + 1
+        };
+        port
+    }
+}
@@ -0,0 +1,444 @@
+//! Unix sockets, which only ever talk to each other inside the kernel.
+//!
+//! A socket is found by the name it is bound to, any string of bytes such as
+//! a path. A stream connection is a pair of pipes, one each way, and a
+//! datagram socket has an inbox the others post to. Whoever waits for room or
+//! data sleeps on the wait queue of the pipe, inbox or listener.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+
+use spin::mutex::SpinMutex;
+
+use crate::{
+    error::Error, fs::File, mm::page_table::UserBuffer, sync::WaitQueue,
+    task::block_current_and_run_next,
+};
+
+use super::{scatter, Socket, SocketAddr, SocketType};
+
+/// Bytes a pipe holds before writers wait.
+const PIPE_CAPACITY: usize = 16 * 1024;
+/// Datagrams an inbox holds before senders wait.
+const INBOX_CAPACITY: usize = 16;
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+/// Most connections a listener holds before they are accepted.
+const MAX_BACKLOG: usize = 16;
+
+/// Bound sockets by name.
+static NAMES: SpinMutex<BTreeMap<Vec<u8>, Arc<SpinMutex<State>>>> = SpinMutex::new(BTreeMap::new());
+
+pub(crate) struct UnixSocket {
+    ty: SocketType,
+    /// Shared with [`NAMES`] while the socket is bound.
+    state: Arc<SpinMutex<State>>,
+}
+
+struct State {
+    name: Option<Vec<u8>>,
+    kind: Kind,
+}
+
+enum Kind {
+    /// A stream socket not connected or listening yet, or a socket closed.
+    Idle,
+    Listening(Listener),
+    Connected(Stream),
+    Datagram(Inbox),
+}
+
+struct Listener {
+    backlog: usize,
+    /// Our ends of the connections made but not accepted yet.
+    pending: VecDeque<Stream>,
+    accepters: WaitQueue,
+    /// Those waiting for room in `pending`.
+    connecters: WaitQueue,
+}
+
+/// One end of a connection.
+struct Stream {
+    rx: Arc<SpinMutex<Pipe>>,
+    tx: Arc<SpinMutex<Pipe>>,
+    /// The name of the other end, empty if it has none.
+    peer: Vec<u8>,
+}
+
+/// The bytes going one way along a connection.
+struct Pipe {
+    data: VecDeque<u8>,
+    /// Nothing more comes once `data` is drained.
+    writer_gone: bool,
+    /// Nothing written is ever read.
+    reader_gone: bool,
+    readers: WaitQueue,
+    writers: WaitQueue,
+}
+
+/// The datagrams sent to a socket, with the name of each sender.
+struct Inbox {
+    datagrams: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// Where to send when not told.
+    peer: Option<Vec<u8>>,
+    receivers: WaitQueue,
+    senders: WaitQueue,
+}
+
+impl Stream {
+    /// Both ends of a new connection, each given the name of the other.
+    fn pair(name_a: Vec<u8>, name_b: Vec<u8>) -> (Stream, Stream) {
+        let a_to_b = Arc::new(SpinMutex::new(Pipe::new()));
+        let b_to_a = Arc::new(SpinMutex::new(Pipe::new()));
+        let a = Stream {
+            rx: b_to_a.clone(),
+            tx: a_to_b.clone(),
+            peer: name_b,
+        };
+        let b = Stream {
+            rx: a_to_b,
+            tx: b_to_a,
+            peer: name_a,
+        };
+        (a, b)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let mut tx = self.tx.lock();
+        tx.writer_gone = true;
+        tx.readers.wake_all();
+        drop(tx);
+        let mut rx = self.rx.lock();
+        rx.reader_gone = true;
+        rx.writers.wake_all();
+    }
+}
+
+impl Pipe {
+    fn new() -> Self {
+        Self {
+            data: VecDeque::new(),
+            writer_gone: false,
+            reader_gone: false,
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }
+    }
+}
+
+/// Read what `pipe` has into `buf`, waiting for something. Returns 0 once the
+/// writer is gone and everything is read.
+fn read_pipe(pipe: &SpinMutex<Pipe>, buf: &mut UserBuffer) -> usize {
+    if buf.len() == 0 {
+        return 0;
+    }
+    loop {
+        let mut inner = pipe.lock();
+        if !inner.data.is_empty() {
+            let mut received = 0;
+            for buffer in buf.buffers.iter_mut() {
+                let len = buffer.len().min(inner.data.len());
+                for (dst, src) in buffer.iter_mut().zip(inner.data.drain(..len)) {
+                    *dst = src;
+                }
+                received += len;
+            }
+            inner.writers.wake_all();
+            return received;
+        }
+        if inner.writer_gone {
+            return 0;
+        }
+        inner.readers.push_current();
+        drop(inner);
+        block_current_and_run_next();
+    }
+}
+
+/// Write all of `buf` to `pipe`, waiting for room. Fails if the reader is
+/// gone before anything was written.
+fn write_pipe(pipe: &SpinMutex<Pipe>, buf: &UserBuffer) -> Result<usize, Error> {
+    let mut sent = 0;
+    for buffer in buf.buffers.iter() {
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let mut inner = pipe.lock();
+            if inner.reader_gone {
+                return match sent + offset {
+                    0 => Err(Error::IoError),
+                    sent => Ok(sent),
+                };
+            }
+            let room = PIPE_CAPACITY - inner.data.len();
+            if room == 0 {
+                inner.writers.push_current();
+                drop(inner);
+                block_current_and_run_next();
+                continue;
+            }
+            let len = room.min(buffer.len() - offset);
+            inner.data.extend(&buffer[offset..offset + len]);
+            inner.readers.wake_all();
+            offset += len;
+        }
+        sent += offset;
+    }
+    Ok(sent)
+}
+
+/// The socket bound to `name`.
+fn lookup(name: &[u8]) -> Result<Arc<SpinMutex<State>>, Error> {
+    NAMES.lock().get(name).cloned().ok_or(Error::InvalidArgs)
+}
+
+fn unix(addr: SocketAddr) -> Result<Vec<u8>, Error> {
+    match addr {
+        SocketAddr::Unix(name) if !name.is_empty() => Ok(name),
+        _ => Err(Error::InvalidArgs),
+    }
+}
+
+impl UnixSocket {
+    pub(crate) fn new(ty: SocketType) -> Self {
+        let kind = match ty {
+            SocketType::Stream => Kind::Idle,
+            SocketType::Datagram => Kind::Datagram(Inbox {
+                datagrams: VecDeque::new(),
+                peer: None,
+                receivers: WaitQueue::new(),
+                senders: WaitQueue::new(),
+            }),
+        };
+        Self {
+            ty,
+            state: Arc::new(SpinMutex::new(State { name: None, kind })),
+        }
+    }
+
+    fn send_datagram(&self, buf: UserBuffer, to: Option<SocketAddr>) -> Result<usize, Error> {
+        if buf.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        let data = buf.buffers.concat();
+        let state = self.state.lock();
+        let Kind::Datagram(inbox) = &state.kind else {
+            return Err(Error::InvalidArgs);
+        };
+        let to = match to {
+            Some(to) => unix(to)?,
+            None => inbox.peer.clone().ok_or(Error::InvalidArgs)?,
+        };
+        let from = state.name.clone().unwrap_or_default();
+        // it may be ourselves
+        drop(state);
+
+        let target = lookup(&to)?;
+        loop {
+            let mut state = target.lock();
+            let Kind::Datagram(inbox) = &mut state.kind else {
+                return Err(Error::IoError);
+            };
+            if inbox.datagrams.len() < INBOX_CAPACITY {
+                let len = data.len();
+                inbox.datagrams.push_back((data, from));
+                inbox.receivers.wake_one();
+                return Ok(len);
+            }
+            inbox.senders.push_current();
+            drop(state);
+            block_current_and_run_next();
+        }
+    }
+
+    fn recv_datagram(&self, mut buf: UserBuffer) -> Result<(usize, SocketAddr), Error> {
+        loop {
+            let mut state = self.state.lock();
+            let Kind::Datagram(inbox) = &mut state.kind else {
+                return Err(Error::InvalidArgs);
+            };
+            if let Some((data, from)) = inbox.datagrams.pop_front() {
+                inbox.senders.wake_one();
+                drop(state);
+                // the rest of a datagram longer than `buf` is lost
+                scatter(&mut buf, &data);
+                return Ok((data.len().min(buf.len()), SocketAddr::Unix(from)));
+            }
+            inbox.receivers.push_current();
+            drop(state);
+            block_current_and_run_next();
+        }
+    }
+}
+
+impl Socket for UnixSocket {
+    /// Names are unique: a name is taken until its socket is closed.
+    fn bind(&self, addr: SocketAddr) -> Result<(), Error> {
+        let name = unix(addr)?;
+        let mut names = NAMES.lock();
+        let mut state = self.state.lock();
+        if state.name.is_some() || names.contains_key(&name) {
+            return Err(Error::InvalidArgs);
+        }
+        state.name = Some(name.clone());
+        names.insert(name, self.state.clone());
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> Result<(), Error> {
+        let mut state = self.state.lock();
+        if state.name.is_none()
+            || !matches!(state.kind, Kind::Idle)
+            || self.ty != SocketType::Stream
+        {
+            return Err(Error::InvalidArgs);
+        }
+        state.kind = Kind::Listening(Listener {
+            backlog: backlog.clamp(1, MAX_BACKLOG),
+            pending: VecDeque::new(),
+            accepters: WaitQueue::new(),
+            connecters: WaitQueue::new(),
+        });
+        Ok(())
+    }
+
+    fn accept(&self) -> Result<(Arc<dyn File>, SocketAddr), Error> {
+        loop {
+            let mut state = self.state.lock();
+            let Kind::Listening(listener) = &mut state.kind else {
+                return Err(Error::InvalidArgs);
+            };
+            if let Some(stream) = listener.pending.pop_front() {
+                listener.connecters.wake_one();
+                let peer = SocketAddr::Unix(stream.peer.clone());
+                let connection = UnixSocket {
+                    ty: SocketType::Stream,
+                    state: Arc::new(SpinMutex::new(State {
+                        name: None,
+                        kind: Kind::Connected(stream),
+                    })),
+                };
+                return Ok((Arc::new(connection), peer));
+            }
+            listener.accepters.push_current();
+            drop(state);
+            block_current_and_run_next();
+        }
+    }
+
+    /// A stream socket waits while the backlog of the listener is full.
+    fn connect(&self, addr: SocketAddr) -> Result<(), Error> {
+        let name = unix(addr)?;
+        let target = lookup(&name)?;
+        let mut state = self.state.lock();
+        match &mut state.kind {
+            Kind::Datagram(inbox) => {
+                inbox.peer = Some(name);
+                return Ok(());
+            }
+            Kind::Idle => {}
+            _ => return Err(Error::InvalidArgs),
+        }
+        let (ours, theirs) = Stream::pair(state.name.clone().unwrap_or_default(), name);
+        drop(state);
+
+        let mut theirs = Some(theirs);
+        loop {
+            let mut target = target.lock();
+            let Kind::Listening(listener) = &mut target.kind else {
+                // nobody listens there
+                return Err(Error::IoError);
+            };
+            if listener.pending.len() < listener.backlog {
+                listener.pending.push_back(theirs.take().unwrap());
+                listener.accepters.wake_one();
+                break;
+            }
+            listener.connecters.push_current();
+            drop(target);
+            block_current_and_run_next();
+        }
+
+        let mut state = self.state.lock();
+        // another thread of ours connected it meanwhile, ours goes unused
+        if !matches!(state.kind, Kind::Idle) {
+            return Err(Error::InvalidArgs);
+        }
+        state.kind = Kind::Connected(ours);
+        Ok(())
+    }
+
+    fn send(&self, buf: UserBuffer, to: Option<SocketAddr>) -> Result<usize, Error> {
+        if self.ty == SocketType::Datagram {
+            return self.send_datagram(buf, to);
+        }
+        let state = self.state.lock();
+        let Kind::Connected(stream) = &state.kind else {
+            return Err(Error::InvalidArgs);
+        };
+        let tx = stream.tx.clone();
+        drop(state);
+        write_pipe(&tx, &buf)
+    }
+
+    fn recv(&self, mut buf: UserBuffer) -> Result<(usize, SocketAddr), Error> {
+        if self.ty == SocketType::Datagram {
+            return self.recv_datagram(buf);
+        }
+        let state = self.state.lock();
+        let Kind::Connected(stream) = &state.kind else {
+            return Err(Error::InvalidArgs);
+        };
+        let rx = stream.rx.clone();
+        let peer = SocketAddr::Unix(stream.peer.clone());
+        drop(state);
+        Ok((read_pipe(&rx, &mut buf), peer))
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.recv(buf).map_or(0, |(len, _)| len)
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.send(buf, None).unwrap_or(0)
+    }
+
+    fn socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        let name = state.name.take();
+        let kind = core::mem::replace(&mut state.kind, Kind::Idle);
+        drop(state);
+        // those waiting on us find us closed; connections go with their ends
+        match kind {
+            Kind::Listening(mut listener) => {
+                listener.connecters.wake_all();
+            }
+            Kind::Datagram(mut inbox) => {
+                inbox.senders.wake_all();
+            }
+            Kind::Idle | Kind::Connected(_) => {}
+        }
+        if let Some(name) = name {
+            NAMES.lock().remove(&name);
+        }
+    }
+}
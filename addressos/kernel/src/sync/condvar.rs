@@ -0,0 +1,34 @@
+use spin::mutex::SpinMutex;
+
+use crate::{error::Error, task::block_current_and_run_next};
+
+use super::{Mutex, WaitQueue};
+
+/// Condition variable, always used together with a [`Mutex`].
+pub(crate) struct Condvar {
+    wait_queue: SpinMutex<WaitQueue>,
+}
+
+impl Condvar {
+    pub(crate) const fn new() -> Self {
+        Self {
+            wait_queue: SpinMutex::new(WaitQueue::new()),
+        }
+    }
+
+    pub(crate) fn signal(&self) {
+        self.wait_queue.lock().wake_one();
+    }
+
+    /// Atomically release `mutex`, held by thread `tid`, and sleep, then take
+    /// `mutex` again once woken up. Fails if `tid` does not hold `mutex`.
+    pub(crate) fn wait(&self, mutex: &Mutex, tid: usize) -> Result<(), Error> {
+        let mut wait_queue = self.wait_queue.lock();
+        mutex.unlock(tid)?;
+        wait_queue.push_current();
+        drop(wait_queue);
+        block_current_and_run_next();
+        mutex.lock(tid);
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use alloc::{vec, vec::Vec};
+
+/// Banker's-algorithm bookkeeping for one kind of resource of a process.
+///
+/// Rows are indexed by thread id, columns by resource id. A request is only
+/// granted when every thread can still run to completion afterwards.
+pub(crate) struct DeadlockDetector {
+    available: Vec<usize>,
+    allocation: Vec<Vec<usize>>,
+    need: Vec<Vec<usize>>,
+}
+
+impl DeadlockDetector {
+    pub(crate) const fn new() -> Self {
+        Self {
+            available: Vec::new(),
+            allocation: Vec::new(),
+            need: Vec::new(),
+        }
+    }
+
+    fn resize(&mut self, tid: usize) {
+        let res_num = self.available.len();
+        if self.allocation.len() <= tid {
+            self.allocation.resize(tid + 1, Vec::new());
+            self.need.resize(tid + 1, Vec::new());
+        }
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            row.resize(res_num, 0);
+        }
+    }
+
+    /// Register resource `id` with `count` free units.
+    pub(crate) fn add_resource(&mut self, id: usize, count: usize) {
+        if self.available.len() <= id {
+            self.available.resize(id + 1, 0);
+        }
+        self.available[id] = count;
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            row.resize(self.available.len(), 0);
+            row[id] = 0;
+        }
+    }
+
+    /// Record that `tid` waits for one unit of `id`.
+    pub(crate) fn request(&mut self, tid: usize, id: usize) {
+        self.resize(tid);
+        self.need[tid][id] += 1;
+    }
+
+    /// Withdraw a request recorded with [`request`](Self::request).
+    pub(crate) fn cancel(&mut self, tid: usize, id: usize) {
+        self.need[tid][id] -= 1;
+    }
+
+    /// Turn a pending request of `tid` into an allocation.
+    pub(crate) fn acquire(&mut self, tid: usize, id: usize) {
+        self.resize(tid);
+        self.need[tid][id] = self.need[tid][id].saturating_sub(1);
+        self.allocation[tid][id] += 1;
+        self.available[id] = self.available[id].saturating_sub(1);
+    }
+
+    /// Give back one unit of `id`. A semaphore may be released by a thread
+    /// that never acquired it, so the allocation is allowed to be zero.
+    pub(crate) fn release(&mut self, tid: usize, id: usize) {
+        self.resize(tid);
+        self.allocation[tid][id] = self.allocation[tid][id].saturating_sub(1);
+        self.available[id] += 1;
+    }
+
+    /// Whether every thread can still finish given the outstanding needs.
+    pub(crate) fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut finish = vec![false; self.need.len()];
+
+        loop {
+            let runnable = (0..self.need.len()).find(|&tid| {
+                !finish[tid]
+                    && self.need[tid]
+                        .iter()
+                        .zip(work.iter())
+                        .all(|(need, work)| need <= work)
+            });
+            match runnable {
+                Some(tid) => {
+                    finish[tid] = true;
+                    for (work, allocation) in work.iter_mut().zip(self.allocation[tid].iter()) {
+                        *work += allocation;
+                    }
+                }
+                None => return finish.iter().all(|&finished| finished),
+            }
+        }
+    }
+}
@@ -1,4 +1,14 @@
+mod condvar;
+mod deadlock;
+mod mutex;
+mod semaphore;
 mod spin;
 mod up;
+mod wait_queue;
 
-pub(crate) use self::up::UpSafeCell;
\ No newline at end of file
+pub(crate) use self::condvar::Condvar;
+pub(crate) use self::deadlock::DeadlockDetector;
+pub(crate) use self::mutex::Mutex;
+pub(crate) use self::semaphore::Semaphore;
+pub(crate) use self::up::UpSafeCell;
+pub(crate) use self::wait_queue::WaitQueue;
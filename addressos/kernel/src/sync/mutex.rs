@@ -0,0 +1,62 @@
+use spin::mutex::SpinMutex;
+
+use crate::{error::Error, task::block_current_and_run_next};
+
+use super::WaitQueue;
+
+/// Sleeping mutex. Contended lockers block instead of spinning.
+pub(crate) struct Mutex {
+    inner: SpinMutex<MutexInner>,
+}
+
+struct MutexInner {
+    locked: bool,
+    /// The thread holding the mutex, `None` while it is handed over to a
+    /// woken waiter that has not run yet.
+    owner: Option<usize>,
+    wait_queue: WaitQueue,
+}
+
+impl Mutex {
+    pub(crate) const fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(MutexInner {
+                locked: false,
+                owner: None,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Take the mutex for thread `tid`, sleeping while another thread holds it.
+    pub(crate) fn lock(&self, tid: usize) {
+        let mut inner = self.inner.lock();
+        if inner.locked {
+            inner.wait_queue.push_current();
+            drop(inner);
+            block_current_and_run_next();
+            // `unlock` handed the mutex over to us without clearing `locked`.
+            inner = self.inner.lock();
+        } else {
+            inner.locked = true;
+        }
+        inner.owner = Some(tid);
+    }
+
+    pub(crate) fn is_held_by(&self, tid: usize) -> bool {
+        self.inner.lock().owner == Some(tid)
+    }
+
+    /// Release the mutex held by thread `tid`. Fails if `tid` does not hold it.
+    pub(crate) fn unlock(&self, tid: usize) -> Result<(), Error> {
+        let mut inner = self.inner.lock();
+        if inner.owner != Some(tid) {
+            return Err(Error::AccessDenied);
+        }
+        inner.owner = None;
+        if !inner.wait_queue.wake_one() {
+            inner.locked = false;
+        }
+        Ok(())
+    }
+}
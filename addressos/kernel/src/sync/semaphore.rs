@@ -0,0 +1,45 @@
+use spin::mutex::SpinMutex;
+
+use crate::task::block_current_and_run_next;
+
+use super::WaitQueue;
+
+/// Counting semaphore.
+pub(crate) struct Semaphore {
+    inner: SpinMutex<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    /// Free units. Units given back while tasks wait go straight to one of them.
+    count: usize,
+    wait_queue: WaitQueue,
+}
+
+impl Semaphore {
+    pub(crate) const fn new(res_count: usize) -> Self {
+        Self {
+            inner: SpinMutex::new(SemaphoreInner {
+                count: res_count,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn up(&self) {
+        let mut inner = self.inner.lock();
+        if !inner.wait_queue.wake_one() {
+            inner.count += 1;
+        }
+    }
+
+    pub(crate) fn down(&self) {
+        let mut inner = self.inner.lock();
+        if inner.count > 0 {
+            inner.count -= 1;
+        } else {
+            inner.wait_queue.push_current();
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use crate::task::{mark_current_blocked, task::TaskControlBlock, wakeup_task};
+
+/// FIFO of tasks sleeping on a kernel object.
+///
+/// A wait queue is always embedded in the locked state of its owner: the owner
+/// enqueues the current task with [`WaitQueue::push_current`], releases its lock
+/// and then calls [`block_current_and_run_next`](crate::task::block_current_and_run_next).
+/// The task is marked `Blocked` while the owner is still locked, so a waker on
+/// another hart can never miss it.
+pub(crate) struct WaitQueue {
+    queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn push_current(&mut self) {
+        self.queue.push_back(mark_current_blocked());
+    }
+
+    /// Wake up the task that has waited the longest, skipping killed ones.
+    /// Returns whether a task was woken.
+    pub(crate) fn wake_one(&mut self) -> bool {
+        while let Some(task) = self.queue.pop_front() {
+            if wakeup_task(task) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Wake up every waiting task. Returns how many were woken.
+    pub(crate) fn wake_all(&mut self) -> usize {
+        self.queue
+            .drain(..)
+            .map(wakeup_task)
+            .filter(|&woken| woken)
+            .count()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -1,19 +1,104 @@
-use crate::task::current_user_token;
-
-const FD_STDOUT: usize = 1;
-
-// write buf of length `len`  to a file with `fd`
-// pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-//     match fd {
-//         FD_STDOUT => {
-//             let buffers = translated_byte_buffer(current_user_token(), buf, len);
-//             for buffer in buffers {
-//                 print!("{}", core::str::from_utf8(buffer).unwrap());
-//             }
-//             len as isize
-//         }
-//         _ => {
-//             panic!("Unsupported fd in sys_write!");
-//         }
-//     }
-// }
\ No newline at end of file
+use alloc::sync::Arc;
+
+use crate::{
+    arch::mm::PageTableFlags,
+    fs::{RamFile, SeekFrom},
+    task::{current_process, current_user_buffer},
+};
+
+const SEEK_SET: usize = 0;
+const SEEK_CUR: usize = 1;
+const SEEK_END: usize = 2;
+
+/// Close `fd`. What it refers to goes away with its last fd.
+pub fn sys_close(fd: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(slot @ Some(_)) = process_inner.fd_table.get_mut(fd) else {
+        return -1;
+    };
+    let file = slot.take();
+    // a socket says goodbye to its peer as it is dropped
+    drop(process_inner);
+    drop(file);
+    0
+}
+
+/// read up to `len` bytes into `buf` from the file with `fd`
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let Some(Some(file)) = process_inner.fd_table.get(fd).cloned() else {
+        return -1;
+    };
+    if !file.readable() {
+        return -1;
+    }
+    // reading may block, so do not keep the process locked
+    drop(process_inner);
+    drop(process);
+
+    let Ok(buf) = current_user_buffer(buf, len, PageTableFlags::Write) else {
+        return -1;
+    };
+    file.read(buf) as isize
+}
+
+/// write buf of length `len`  to a file with `fd`
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let Some(Some(file)) = process_inner.fd_table.get(fd).cloned() else {
+        return -1;
+    };
+    if !file.writable() {
+        return -1;
+    }
+    drop(process_inner);
+    drop(process);
+
+    let Ok(buf) = current_user_buffer(buf, len, PageTableFlags::Read) else {
+        return -1;
+    };
+    file.write(buf) as isize
+}
+
+/// Create an empty file living in memory only and return its fd. The name is
+/// not used, and `flags` must be 0.
+pub fn sys_memfd_create(_name: *const u8, flags: usize) -> isize {
+    if flags != 0 {
+        return -1;
+    }
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let fd = process_inner.alloc_fd();
+    process_inner.fd_table[fd] = Some(Arc::new(RamFile::new()));
+    fd as isize
+}
+
+/// Move the offset of `fd` by `offset` from the start (`SEEK_SET`), from the
+/// current offset (`SEEK_CUR`) or from the end (`SEEK_END`), and return the
+/// new offset.
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let Some(Some(file)) = process_inner.fd_table.get(fd).cloned() else {
+        return -1;
+    };
+    drop(process_inner);
+    drop(process);
+
+    let pos = match whence {
+        SEEK_SET => match usize::try_from(offset) {
+            Ok(offset) => SeekFrom::Start(offset),
+            Err(_) => return -1,
+        },
+        SEEK_CUR => SeekFrom::Current(offset),
+        SEEK_END => SeekFrom::End(offset),
+        _ => return -1,
+    };
+    match file.seek(pos).map(isize::try_from) {
+        Some(Ok(offset)) => offset,
+        _ => -1,
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    arch::mm::{paging_levels, PageTableFlags},
+    config::{PAGE_SIZE, PAGE_SIZE_BITS},
+    mm::{
+        address::VirtAddr,
+        is_page_aligned,
+        memory_set::{FileBacking, MapArea, MapType, MemorySet},
+        page_table::PageTableFlagsTrait,
+        shm,
+    },
+    task::current_process,
+};
+
+const PROT_READ: usize = 1 << 0;
+const PROT_WRITE: usize = 1 << 1;
+const PROT_EXEC: usize = 1 << 2;
+
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_ANONYMOUS: usize = 0x20;
+
+/// End of the lower half of the address space, the part users may map.
+fn user_space_end() -> usize {
+    1 << (PAGE_SIZE_BITS + 9 * paging_levels() - 1)
+}
+
+/// The flags of a user mapping with protection `prot`, if `prot` makes sense.
+fn prot_to_flags(prot: usize) -> Option<PageTableFlags> {
+    if prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return None;
+    }
+    Some(
+        PageTableFlags::new()
+            .set_valid(true)
+            .set_accessible_by_user(true)
+            .set_readable(prot & PROT_READ != 0)
+            .set_writable(prot & PROT_WRITE != 0)
+            .set_executable(prot & PROT_EXEC != 0),
+    )
+}
+
+/// Whether the `len` bytes from `start` lie in user space with nothing mapped there.
+fn is_free_user_range(memory_set: &MemorySet, start: usize, len: usize) -> bool {
+    let user_space_end = user_space_end();
+    if !is_page_aligned(start) || len > user_space_end || start > user_space_end - len {
+        return false;
+    }
+    !(start..start + len)
+        .step_by(PAGE_SIZE)
+        .any(|va| memory_set.is_mapped(VirtAddr::from(va)))
+}
+
+/// Map `len` bytes at the page aligned `start`: fresh zeroed memory with
+/// `MAP_ANONYMOUS`, or the file `fd` from the page aligned `offset` on.
+/// `flags` must have one of `MAP_SHARED` and `MAP_PRIVATE`; writes to a private
+/// mapping of a file are not seen by anyone else. Anonymous memory is always
+/// private, shared memory comes from `sys_shm_create`.
+///
+/// Memory is overcommitted: pages come on the first access, and may be swapped
+/// out or dropped later.
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    let Some(pt_flags) = prot_to_flags(prot) else {
+        return -1;
+    };
+    if len == 0
+        || flags & !(MAP_SHARED | MAP_PRIVATE | MAP_ANONYMOUS) != 0
+        || (flags & MAP_SHARED != 0) == (flags & MAP_PRIVATE != 0)
+        || flags & (MAP_SHARED | MAP_ANONYMOUS) == MAP_SHARED | MAP_ANONYMOUS
+    {
+        return -1;
+    }
+    let Some(len) = len.checked_next_multiple_of(PAGE_SIZE) else {
+        return -1;
+    };
+
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let backing = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        let Some(Some(file)) = process_inner.fd_table.get(fd) else {
+            return -1;
+        };
+        let Some(inode) = file.inode() else {
+            return -1;
+        };
+        let shared = flags & MAP_SHARED != 0;
+        if !is_page_aligned(offset)
+            || !file.readable()
+            || (shared && prot & PROT_WRITE != 0 && !file.writable())
+        {
+            return -1;
+        }
+        Some(FileBacking {
+            inode,
+            offset,
+            shared,
+        })
+    };
+    let memory_set = &mut process_inner.memory_set;
+    if !is_free_user_range(memory_set, start, len) {
+        return -1;
+    }
+    // the pages are only read in or allocated when first touched
+    let mut area = MapArea::new(VirtAddr::from(start), len, pt_flags, MapType::Framed);
+    area.backing = backing;
+    memory_set.map(area);
+    0
+}
+
+/// Write the pages of shared file mappings in the `len` bytes from `start`
+/// back to their files.
+pub fn sys_msync(start: usize, len: usize) -> isize {
+    let Some(end) = start.checked_add(len) else {
+        return -1;
+    };
+    if !is_page_aligned(start) {
+        return -1;
+    }
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner
+        .memory_set
+        .sync(VirtAddr::from(start), VirtAddr::from(end));
+    0
+}
+
+/// Unmap a region created by `sys_mmap`, which must be given back as a whole.
+/// Pages written through a shared file mapping go back to the file.
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    let Some(len) = len.checked_next_multiple_of(PAGE_SIZE) else {
+        return -1;
+    };
+
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let memory_set = &mut process_inner.memory_set;
+    match memory_set.area_at(VirtAddr::from(start)) {
+        Some(area) if area.size == len && !memory_set.is_shm(VirtAddr::from(start)) => {}
+        _ => return -1,
+    }
+    match memory_set.unmap(VirtAddr::from(start)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Create a shared memory segment of `size` bytes and return its handle.
+///
+/// The segment stays until `sys_shm_destroy` is called on it and every
+/// attachment to it is detached.
+pub fn sys_shm_create(size: usize) -> isize {
+    if size == 0 {
+        return -1;
+    }
+    match shm::create(size) {
+        Some(handle) => handle as isize,
+        None => -1,
+    }
+}
+
+/// Map the shared memory segment `handle` at the page aligned `start`.
+pub fn sys_shm_attach(handle: usize, start: usize, prot: usize) -> isize {
+    let Some(flags) = prot_to_flags(prot) else {
+        return -1;
+    };
+    let Some((frames, attachment)) = shm::attach(handle) else {
+        return -1;
+    };
+    let len = frames.len() * PAGE_SIZE;
+
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let memory_set = &mut process_inner.memory_set;
+    if !is_free_user_range(memory_set, start, len) {
+        return -1;
+    }
+    let area = MapArea::new_with_frames(VirtAddr::from(start), len, flags, MapType::Framed, frames);
+    memory_set.map_shm(area, attachment);
+    0
+}
+
+/// Destroy the shared memory segment `handle`: it cannot be attached to
+/// anymore, and goes away once the current attachments are detached.
+pub fn sys_shm_destroy(handle: usize) -> isize {
+    match shm::destroy(handle) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Unmap the shared memory segment attached at `start`.
+pub fn sys_shm_detach(start: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let memory_set = &mut process_inner.memory_set;
+    if !memory_set.is_shm(VirtAddr::from(start)) {
+        return -1;
+    }
+    match memory_set.unmap(VirtAddr::from(start)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
@@ -1 +1,127 @@
-pub mod fs;
\ No newline at end of file
+use num_enum::TryFromPrimitive;
+
+use self::{
+    fs::{sys_close, sys_lseek, sys_memfd_create, sys_read, sys_write},
+    mm::{
+        sys_mmap, sys_msync, sys_munmap, sys_shm_attach, sys_shm_create, sys_shm_destroy,
+        sys_shm_detach,
+    },
+    net::{sys_accept, sys_bind, sys_connect, sys_listen, sys_recv, sys_send, sys_socket},
+    process::{sys_exit, sys_sched_yield},
+    sync::{
+        sys_condvar_create, sys_condvar_signal, sys_condvar_wait, sys_enable_deadlock_detect,
+        sys_mutex_create, sys_mutex_lock, sys_mutex_unlock, sys_semaphore_create,
+        sys_semaphore_down, sys_semaphore_up,
+    },
+    thread::{sys_gettid, sys_thread_create, sys_waittid},
+    time::{sys_clock_gettime, sys_gettimeofday, sys_nanosleep},
+};
+
+pub mod fs;
+pub mod mm;
+pub mod net;
+pub mod process;
+pub mod sync;
+pub mod thread;
+pub mod time;
+
+#[derive(Debug, TryFromPrimitive)]
+#[repr(usize)]
+pub(crate) enum Syscall {
+    Close = 57,
+    Lseek = 62,
+    Read = 63,
+    Write = 64,
+    Exit = 93,
+    Nanosleep = 101,
+    ClockGettime = 113,
+    SchedYield = 124,
+    Gettimeofday = 169,
+    ShmCreate = 194,
+    ShmDestroy = 195,
+    ShmAttach = 196,
+    ShmDetach = 197,
+    Socket = 198,
+    Bind = 200,
+    Listen = 201,
+    Accept = 202,
+    Connect = 203,
+    Send = 206,
+    Recv = 207,
+    Munmap = 215,
+    Mmap = 222,
+    Msync = 227,
+    MemfdCreate = 279,
+    EnableDeadlockDetect = 469,
+    ThreadCreate = 1000,
+    Gettid = 1001,
+    Waittid = 1002,
+    MutexCreate = 1010,
+    MutexLock = 1011,
+    MutexUnlock = 1012,
+    SemaphoreCreate = 1020,
+    SemaphoreUp = 1021,
+    SemaphoreDown = 1022,
+    CondvarCreate = 1030,
+    CondvarSignal = 1031,
+    CondvarWait = 1032,
+}
+
+pub(crate) fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    match Syscall::try_from(syscall_id) {
+        Ok(Syscall::Close) => sys_close(args[0]),
+        Ok(Syscall::Lseek) => sys_lseek(args[0], args[1] as isize, args[2]),
+        Ok(Syscall::Read) => sys_read(args[0], args[1] as *const u8, args[2]),
+        Ok(Syscall::Write) => sys_write(args[0], args[1] as *const u8, args[2]),
+        Ok(Syscall::Exit) => sys_exit(args[0] as i32),
+        Ok(Syscall::Nanosleep) => sys_nanosleep(args[0] as *const _),
+        Ok(Syscall::ClockGettime) => sys_clock_gettime(args[0], args[1] as *mut _),
+        Ok(Syscall::SchedYield) => sys_sched_yield(),
+        Ok(Syscall::Gettimeofday) => sys_gettimeofday(args[0] as *mut _, args[1]),
+        Ok(Syscall::ShmCreate) => sys_shm_create(args[0]),
+        Ok(Syscall::ShmDestroy) => sys_shm_destroy(args[0]),
+        Ok(Syscall::ShmAttach) => sys_shm_attach(args[0], args[1], args[2]),
+        Ok(Syscall::ShmDetach) => sys_shm_detach(args[0]),
+        Ok(Syscall::Socket) => sys_socket(args[0], args[1], args[2]),
+        Ok(Syscall::Bind) => sys_bind(args[0], args[1] as *const _, args[2]),
+        Ok(Syscall::Listen) => sys_listen(args[0], args[1]),
+        Ok(Syscall::Accept) => sys_accept(args[0], args[1] as *mut _, args[2] as *mut _),
+        Ok(Syscall::Connect) => sys_connect(args[0], args[1] as *const _, args[2]),
+        Ok(Syscall::Send) => sys_send(
+            args[0],
+            args[1] as *const _,
+            args[2],
+            args[3],
+            args[4] as *const _,
+            args[5],
+        ),
+        Ok(Syscall::Recv) => sys_recv(
+            args[0],
+            args[1] as *mut _,
+            args[2],
+            args[3],
+            args[4] as *mut _,
+            args[5] as *mut _,
+        ),
+        Ok(Syscall::Munmap) => sys_munmap(args[0], args[1]),
+        Ok(Syscall::Mmap) => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        Ok(Syscall::Msync) => sys_msync(args[0], args[1]),
+        Ok(Syscall::MemfdCreate) => sys_memfd_create(args[0] as *const _, args[1]),
+        Ok(Syscall::EnableDeadlockDetect) => sys_enable_deadlock_detect(args[0]),
+        Ok(Syscall::ThreadCreate) => sys_thread_create(args[0], args[1]),
+        Ok(Syscall::Gettid) => sys_gettid(),
+        Ok(Syscall::Waittid) => sys_waittid(args[0]),
+        Ok(Syscall::MutexCreate) => sys_mutex_create(),
+        Ok(Syscall::MutexLock) => sys_mutex_lock(args[0]),
+        Ok(Syscall::MutexUnlock) => sys_mutex_unlock(args[0]),
+        Ok(Syscall::SemaphoreCreate) => sys_semaphore_create(args[0]),
+        Ok(Syscall::SemaphoreUp) => sys_semaphore_up(args[0]),
+        Ok(Syscall::SemaphoreDown) => sys_semaphore_down(args[0]),
+        Ok(Syscall::CondvarCreate) => sys_condvar_create(),
+        Ok(Syscall::CondvarSignal) => sys_condvar_signal(args[0]),
+        Ok(Syscall::CondvarWait) => sys_condvar_wait(args[0], args[1]),
+        Err(e) => {
+            panic!("syscall_id not found: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+use crate::{
+    arch::mm::PageTableFlags,
+    error::Error,
+    fs::File,
+    net::{InetSocket, SocketAddr, SocketType, UnixSocket},
+    task::{copy_from_current_user, copy_to_current_user, current_process, current_user_buffer},
+};
+
+const AF_UNIX: u16 = 1;
+const AF_INET: u16 = 2;
+
+const SOCK_STREAM: usize = 1;
+const SOCK_DGRAM: usize = 2;
+
+/// `sockaddr_in`: the family, then the port and the address in network byte
+/// order, then padding.
+const SOCKADDR_IN_LEN: usize = 16;
+/// `sockaddr_un`: the family, then a name of up to 108 bytes ending at the
+/// first NUL or at the length given.
+const SOCKADDR_UN_LEN: usize = 110;
+
+/// The socket address of `addrlen` bytes at `addr`.
+fn read_addr(addr: *const u8, addrlen: usize) -> Result<SocketAddr, Error> {
+    if addrlen < 2 {
+        return Err(Error::InvalidArgs);
+    }
+    let bytes = current_user_buffer(addr, addrlen.min(SOCKADDR_UN_LEN), PageTableFlags::Read)?
+        .buffers
+        .concat();
+    match u16::from_ne_bytes([bytes[0], bytes[1]]) {
+        AF_INET if bytes.len() >= SOCKADDR_IN_LEN => Ok(SocketAddr::Inet(IpEndpoint::new(
+            IpAddress::Ipv4(Ipv4Address::from_bytes(&bytes[4..8])),
+            u16::from_be_bytes([bytes[2], bytes[3]]),
+        ))),
+        AF_UNIX => {
+            let path = &bytes[2..];
+            let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            Ok(SocketAddr::Unix(path[..len].to_vec()))
+        }
+        _ => Err(Error::InvalidArgs),
+    }
+}
+
+/// Write `from` to `addr`, as much as the `*addrlen` bytes there hold, and
+/// its whole size to `addrlen`. Nothing is written if `addr` is null.
+fn write_addr(addr: *mut u8, addrlen: *mut u32, from: SocketAddr) -> Result<(), Error> {
+    if addr.is_null() {
+        return Ok(());
+    }
+    let room = copy_from_current_user(addrlen)? as usize;
+    let mut bytes = Vec::new();
+    match from {
+        SocketAddr::Inet(endpoint) => {
+            let IpAddress::Ipv4(ip) = endpoint.addr;
+            bytes.extend(AF_INET.to_ne_bytes());
+            bytes.extend(endpoint.port.to_be_bytes());
+            bytes.extend(ip.0);
+            bytes.resize(SOCKADDR_IN_LEN, 0);
+        }
+        SocketAddr::Unix(name) => {
+            bytes.extend(AF_UNIX.to_ne_bytes());
+            // an unnamed socket has the family alone
+            if !name.is_empty() {
+                bytes.extend(name);
+                bytes.push(0);
+            }
+        }
+    }
+    let mut buf = current_user_buffer(addr, bytes.len().min(room), PageTableFlags::Write)?;
+    let mut copied = 0;
+    for buffer in buf.buffers.iter_mut() {
+        buffer.copy_from_slice(&bytes[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    copy_to_current_user(addrlen, &(bytes.len() as u32))
+}
+
+/// The file of `fd`, if it is a socket.
+fn socket_file(fd: usize) -> Option<Arc<dyn File>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let file = process_inner.fd_table.get(fd).cloned().flatten()?;
+    file.socket().is_some().then_some(file)
+}
+
+/// Put `socket` in the file descriptor table, returning its fd.
+fn install(socket: Arc<dyn File>) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let fd = process_inner.alloc_fd();
+    process_inner.fd_table[fd] = Some(socket);
+    fd as isize
+}
+
+/// Create a socket of `domain`, `AF_INET` or `AF_UNIX`, and of `ty`,
+/// `SOCK_STREAM` or `SOCK_DGRAM`. Internet ones are TCP or UDP.
+pub fn sys_socket(domain: usize, ty: usize, _protocol: usize) -> isize {
+    let ty = match ty {
+        SOCK_STREAM => SocketType::Stream,
+        SOCK_DGRAM => SocketType::Datagram,
+        _ => return -1,
+    };
+    match u16::try_from(domain) {
+        Ok(AF_INET) => install(Arc::new(InetSocket::new(ty))),
+        Ok(AF_UNIX) => install(Arc::new(UnixSocket::new(ty))),
+        _ => -1,
+    }
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    match read_addr(addr, addrlen).and_then(|local| file.socket().unwrap().bind(local)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    match file.socket().unwrap().listen(backlog) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Wait for a connection on the listening socket `fd`, and return the fd of
+/// a new socket for it. The peer goes to `addr` unless it is null.
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    let Ok((socket, remote)) = file.socket().unwrap().accept() else {
+        return -1;
+    };
+    if write_addr(addr, addrlen, remote).is_err() {
+        return -1;
+    }
+    install(socket)
+}
+
+pub fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    match read_addr(addr, addrlen).and_then(|remote| file.socket().unwrap().connect(remote)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Send `len` bytes of `buf` on `fd`, to `addr` unless it is null.
+pub fn sys_send(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    _flags: usize,
+    addr: *const u8,
+    addrlen: usize,
+) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    let to = if addr.is_null() {
+        None
+    } else {
+        match read_addr(addr, addrlen) {
+            Ok(to) => Some(to),
+            Err(_) => return -1,
+        }
+    };
+    let Ok(buf) = current_user_buffer(buf, len, PageTableFlags::Read) else {
+        return -1;
+    };
+    match file.socket().unwrap().send(buf, to) {
+        Ok(sent) => sent as isize,
+        Err(_) => -1,
+    }
+}
+
+/// Receive up to `len` bytes into `buf` from `fd`. The sender goes to `addr`
+/// unless it is null.
+pub fn sys_recv(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    _flags: usize,
+    addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    let Some(file) = socket_file(fd) else {
+        return -1;
+    };
+    let Ok(buf) = current_user_buffer(buf, len, PageTableFlags::Write) else {
+        return -1;
+    };
+    let Ok((received, from)) = file.socket().unwrap().recv(buf) else {
+        return -1;
+    };
+    if write_addr(addr, addrlen, from).is_err() {
+        return -1;
+    }
+    received as isize
+}
@@ -0,0 +1,12 @@
+use crate::task::{exit_current_and_run_next, suspend_current_and_run_next};
+
+pub fn sys_exit(exit_code: i32) -> ! {
+    println!("[kernel] Application exited with code {}", exit_code);
+    exit_current_and_run_next(exit_code);
+    panic!("Unreachable in sys_exit!");
+}
+
+pub fn sys_sched_yield() -> isize {
+    suspend_current_and_run_next();
+    0
+}
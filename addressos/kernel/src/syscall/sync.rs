@@ -0,0 +1,179 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    sync::{Condvar, Mutex, Semaphore},
+    task::{current_process, current_tid},
+};
+
+/// Returned by a lock or down request that would leave the process unsafe.
+const EDEADLK: isize = -0xdead;
+
+/// Store `item` in the first free slot of `list` and return its id.
+fn insert_resource<T>(list: &mut Vec<Option<T>>, item: T) -> usize {
+    if let Some(id) = list.iter().position(Option::is_none) {
+        list[id] = Some(item);
+        id
+    } else {
+        list.push(Some(item));
+        list.len() - 1
+    }
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let enabled = match enabled {
+        0 => false,
+        1 => true,
+        _ => return -1,
+    };
+    current_process().inner_exclusive_access().deadlock_detect = enabled;
+    0
+}
+
+pub fn sys_mutex_create() -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_resource(&mut process_inner.mutex_list, Arc::new(Mutex::new()));
+    process_inner.mutex_detector.add_resource(id, 1);
+    id as isize
+}
+
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(Some(mutex)) = process_inner.mutex_list.get(mutex_id).cloned() else {
+        return -1;
+    };
+    process_inner.mutex_detector.request(tid, mutex_id);
+    if process_inner.deadlock_detect && !process_inner.mutex_detector.is_safe() {
+        process_inner.mutex_detector.cancel(tid, mutex_id);
+        return EDEADLK;
+    }
+    drop(process_inner);
+    drop(process);
+
+    mutex.lock(tid);
+
+    let process = current_process();
+    process
+        .inner_exclusive_access()
+        .mutex_detector
+        .acquire(tid, mutex_id);
+    0
+}
+
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(Some(mutex)) = process_inner.mutex_list.get(mutex_id).cloned() else {
+        return -1;
+    };
+    if mutex.unlock(tid).is_err() {
+        return -1;
+    }
+    process_inner.mutex_detector.release(tid, mutex_id);
+    0
+}
+
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_resource(
+        &mut process_inner.semaphore_list,
+        Arc::new(Semaphore::new(res_count)),
+    );
+    process_inner.semaphore_detector.add_resource(id, res_count);
+    id as isize
+}
+
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(Some(sem)) = process_inner.semaphore_list.get(sem_id).cloned() else {
+        return -1;
+    };
+    process_inner.semaphore_detector.release(tid, sem_id);
+    drop(process_inner);
+    drop(process);
+
+    sem.up();
+    0
+}
+
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(Some(sem)) = process_inner.semaphore_list.get(sem_id).cloned() else {
+        return -1;
+    };
+    process_inner.semaphore_detector.request(tid, sem_id);
+    if process_inner.deadlock_detect && !process_inner.semaphore_detector.is_safe() {
+        process_inner.semaphore_detector.cancel(tid, sem_id);
+        return EDEADLK;
+    }
+    drop(process_inner);
+    drop(process);
+
+    sem.down();
+
+    let process = current_process();
+    process
+        .inner_exclusive_access()
+        .semaphore_detector
+        .acquire(tid, sem_id);
+    0
+}
+
+pub fn sys_condvar_create() -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    insert_resource(&mut process_inner.condvar_list, Arc::new(Condvar::new())) as isize
+}
+
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let Some(Some(condvar)) = process_inner.condvar_list.get(condvar_id).cloned() else {
+        return -1;
+    };
+    drop(process_inner);
+    drop(process);
+
+    condvar.signal();
+    0
+}
+
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(Some(condvar)) = process_inner.condvar_list.get(condvar_id).cloned() else {
+        return -1;
+    };
+    let Some(Some(mutex)) = process_inner.mutex_list.get(mutex_id).cloned() else {
+        return -1;
+    };
+    // Only the holder can change that, and the holder is us.
+    if !mutex.is_held_by(tid) {
+        return -1;
+    }
+    // The mutex is given up while sleeping and taken back before returning,
+    // so the detector sees a release followed by a fresh acquisition.
+    process_inner.mutex_detector.release(tid, mutex_id);
+    drop(process_inner);
+    drop(process);
+
+    if condvar.wait(&mutex, tid).is_err() {
+        return -1;
+    }
+
+    let process = current_process();
+    process
+        .inner_exclusive_access()
+        .mutex_detector
+        .acquire(tid, mutex_id);
+    0
+}
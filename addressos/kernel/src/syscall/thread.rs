@@ -0,0 +1,96 @@
+use alloc::sync::Arc;
+
+use crate::{
+    mm::memory_set::KERNEL_SPACE,
+    task::{
+        block_current_and_run_next, current_process, current_task, manager::TASK_MANAGER,
+        task::TaskControlBlock,
+    },
+    trap::{context::TrapContext, trap_handler},
+};
+
+/// Start a new thread in the current process at `entry`, with `arg` in `a0`.
+/// Returns the tid of the new thread, or -1 if there is no memory for it or
+/// its stack would land on a mapping.
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let ustack_base = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .ustack_base;
+
+    let Ok(new_task) = TaskControlBlock::new(Arc::clone(&process), ustack_base, true) else {
+        return -1;
+    };
+    let new_task = Arc::new(new_task);
+    let new_task_inner = new_task.inner_exclusive_access();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        new_task_res.ustack_top(),
+        KERNEL_SPACE.get().unwrap().lock().token(),
+        new_task.kernel_stack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    drop(new_task_inner);
+
+    let mut process_inner = process.inner_exclusive_access();
+    while process_inner.tasks.len() <= new_task_tid {
+        process_inner.tasks.push(None);
+    }
+    process_inner.tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
+
+    TASK_MANAGER.spawn(new_task);
+    new_task_tid as isize
+}
+
+pub fn sys_gettid() -> isize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid as isize
+}
+
+/// Wait for thread `tid` of the current process to exit and return its exit code.
+///
+/// Returns -1 if `tid` is the caller itself or does not name a thread.
+pub fn sys_waittid(tid: usize) -> isize {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    if task.inner_exclusive_access().res.as_ref().unwrap().tid == tid {
+        return -1;
+    }
+    let Some(waited_task) = process.inner_exclusive_access().get_task(tid) else {
+        return -1;
+    };
+    drop(process);
+    drop(task);
+
+    loop {
+        let mut waited_inner = waited_task.inner_exclusive_access();
+        if let Some(exit_code) = waited_inner.exit_code {
+            // another waiter got there first
+            let Some(res) = waited_inner.res.take() else {
+                return -1;
+            };
+            drop(waited_inner);
+            // the thread is gone for good, recycle its slot and then its tid
+            current_process().inner_exclusive_access().tasks[tid] = None;
+            drop(res);
+            return exit_code as isize;
+        }
+        waited_inner.join_queue.push_current();
+        drop(waited_inner);
+        block_current_and_run_next();
+    }
+}
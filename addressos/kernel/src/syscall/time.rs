@@ -0,0 +1,71 @@
+use crate::{
+    task::{copy_from_current_user, copy_to_current_user},
+    timer::{get_time_ns, realtime_ns, sleep},
+};
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+const NSEC_PER_USEC: u64 = 1000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+/// Write the time of `clock_id`, `CLOCK_REALTIME` since the epoch or
+/// `CLOCK_MONOTONIC` since boot, to `ts`.
+pub fn sys_clock_gettime(clock_id: usize, ts: *mut TimeSpec) -> isize {
+    let now = match clock_id {
+        CLOCK_REALTIME => realtime_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return -1,
+    };
+    let time = TimeSpec {
+        sec: (now / NSEC_PER_SEC) as usize,
+        nsec: (now % NSEC_PER_SEC) as usize,
+    };
+    match copy_to_current_user(ts, &time) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Write the time since the epoch to `tv`. There are no time zones.
+pub fn sys_gettimeofday(tv: *mut TimeVal, _tz: usize) -> isize {
+    let now = realtime_ns();
+    let time = TimeVal {
+        sec: (now / NSEC_PER_SEC) as usize,
+        usec: (now % NSEC_PER_SEC / NSEC_PER_USEC) as usize,
+    };
+    match copy_to_current_user(tv, &time) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Sleep for the time in `req`.
+pub fn sys_nanosleep(req: *const TimeSpec) -> isize {
+    let Ok(req) = copy_from_current_user(req) else {
+        return -1;
+    };
+    if req.nsec >= NSEC_PER_SEC as usize {
+        return -1;
+    }
+    // longer than the machine will run anyway
+    let ns = (req.sec as u64)
+        .saturating_mul(NSEC_PER_SEC)
+        .saturating_add(req.nsec as u64);
+    sleep(ns);
+    0
+}
@@ -9,8 +9,12 @@ pub struct TaskContext {
 }
 
 impl TaskContext {
-    pub fn zero_init() -> Self {
-        TaskContext::default()
+    pub const fn zero_init() -> Self {
+        TaskContext {
+            ra: 0,
+            sp: 0,
+            s: [0; 12],
+        }
     }
 
     pub fn goto_trap_return(kstack_ptr: usize) -> Self {
@@ -0,0 +1,145 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::mutex::SpinMutex;
+
+use crate::{arch::cpu::send_ipi, config::MAX_HARTS, cpu::hart_id};
+
+use super::{process::ProcessControlBlock, task::TaskControlBlock};
+
+type ReadyQueue = SpinMutex<VecDeque<Arc<TaskControlBlock>>>;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_QUEUE: ReadyQueue = SpinMutex::new(VecDeque::new());
+
+/// FIFO scheduler over every `Ready` thread, with one run queue per hart.
+///
+/// A hart takes from the front of its own queue and, when that is empty,
+/// steals from the back of another hart's queue.
+pub struct TaskManager {
+    ready_queues: [ReadyQueue; MAX_HARTS],
+    /// Threads that have been spawned and not exited yet, whatever their status.
+    task_count: AtomicUsize,
+    /// Threads currently in the `Blocked` status.
+    blocked_count: AtomicUsize,
+    /// Harts sleeping for want of a task, one bit each.
+    idle_harts: AtomicUsize,
+}
+
+impl TaskManager {
+    pub const fn new() -> Self {
+        Self {
+            ready_queues: [EMPTY_QUEUE; MAX_HARTS],
+            task_count: AtomicUsize::new(0),
+            blocked_count: AtomicUsize::new(0),
+            idle_harts: AtomicUsize::new(0),
+        }
+    }
+
+    /// Register a freshly created thread and make it runnable.
+    pub fn spawn(&self, task: Arc<TaskControlBlock>) {
+        self.task_count.fetch_add(1, Ordering::AcqRel);
+        self.add(task);
+    }
+
+    /// Put a known thread back into the run queue of this hart, and wake up an
+    /// idle hart to steal it.
+    pub fn add(&self, task: Arc<TaskControlBlock>) {
+        let hart_id = hart_id();
+        self.ready_queues[hart_id].lock().push_back(task);
+        let idle_harts = self.idle_harts.load(Ordering::SeqCst) & !(1 << hart_id);
+        if idle_harts != 0 {
+            send_ipi(1 << idle_harts.trailing_zeros());
+        }
+    }
+
+    pub fn has_ready(&self) -> bool {
+        self.ready_queues
+            .iter()
+            .any(|queue| !queue.lock().is_empty())
+    }
+
+    /// Mark this hart as sleeping for want of a task, or awake again. A hart
+    /// marks itself before checking the queues a last time, so that a task
+    /// added meanwhile wakes it up.
+    pub fn set_idle(&self, idle: bool) {
+        let bit = 1 << hart_id();
+        if idle {
+            self.idle_harts.fetch_or(bit, Ordering::SeqCst);
+        } else {
+            self.idle_harts.fetch_and(!bit, Ordering::SeqCst);
+        }
+    }
+
+    pub fn fetch(&self) -> Option<Arc<TaskControlBlock>> {
+        let hart_id = hart_id();
+        if let Some(task) = self.ready_queues[hart_id].lock().pop_front() {
+            return Some(task);
+        }
+        (1..MAX_HARTS)
+            .map(|i| (hart_id + i) % MAX_HARTS)
+            .find_map(|victim| self.ready_queues[victim].lock().pop_back())
+    }
+
+    /// Forget a thread that has exited or has been killed.
+    pub fn remove(&self, task: &Arc<TaskControlBlock>) {
+        for queue in self.ready_queues.iter() {
+            queue.lock().retain(|t| !Arc::ptr_eq(t, task));
+        }
+        self.task_count.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.task_count.load(Ordering::Acquire)
+    }
+
+    /// Account for a thread entering the `Blocked` status.
+    pub fn inc_blocked(&self) {
+        self.blocked_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Account for a thread leaving the `Blocked` status.
+    pub fn dec_blocked(&self) {
+        self.blocked_count.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.blocked_count.load(Ordering::Acquire)
+    }
+}
+
+pub static TASK_MANAGER: TaskManager = TaskManager::new();
+
+/// Every live process, which keeps them alive while only their threads are referenced elsewhere.
+static PID2PCB: SpinMutex<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+    SpinMutex::new(BTreeMap::new());
+
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.add(task);
+}
+
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.fetch()
+}
+
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.lock().get(&pid).map(Arc::clone)
+}
+
+/// Every live process, by increasing pid.
+pub fn all_processes() -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB.lock().values().cloned().collect()
+}
+
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.lock().insert(pid, process);
+}
+
+pub fn remove_from_pid2process(pid: usize) {
+    if PID2PCB.lock().remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
@@ -1,168 +1,279 @@
-use alloc::vec::Vec;
-use spin::{mutex::SpinMutex, Once};
+use alloc::{sync::Arc, vec::Vec};
+use core::mem::size_of;
 
 use crate::{
-    arch::power::shutdown, loader::{get_app_data, get_num_app}, task::{context::TaskContext, switch::__switch, task::TaskStatus}, trap::context::TrapContext
+    arch::mm::PageTableFlags,
+    config::PAGE_SIZE,
+    error::Error,
+    loader::{get_app_data, get_num_app},
+    mm::{address::VirtAddr, option::VirtMemAllocOption, page_table::UserBuffer},
 };
 
-use self::task::TaskControlBlock;
+use self::{
+    context::TaskContext,
+    manager::{add_task, remove_from_pid2process, TASK_MANAGER},
+    process::ProcessControlBlock,
+    processor::{schedule, set_exited_task, take_current_task},
+    task::{TaskControlBlock, TaskStatus},
+};
+
+pub use self::processor::{
+    current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    run_tasks,
+};
 
 pub mod context;
+pub mod manager;
+pub mod pid;
+pub mod process;
+pub mod processor;
 pub mod switch;
 #[allow(clippy::module_inception)]
 pub mod task;
 
-pub struct TaskManager {
-    num_app: usize,
-    inner: SpinMutex<TaskManagerInner>,
+/// Load every application linked into the kernel image as its own process.
+pub fn add_initial_tasks() {
+    let num_app = get_num_app();
+    println!("num_app = {}", num_app);
+
+    for i in 0..num_app {
+        ProcessControlBlock::new(get_app_data(i));
+    }
 }
 
-struct TaskManagerInner {
-    tasks: Vec<TaskControlBlock>,
-    current_task: usize,
+/// Suspend the current 'Running' task and run the next task in task list.
+pub fn suspend_current_and_run_next() {
+    let task = take_current_task().unwrap();
+
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+
+    add_task(task);
+    schedule(task_cx_ptr);
 }
 
-pub static TASK_MANAGER: Once<TaskManager> = Once::new();
+/// Switch away from the current task, which has already been marked `Blocked`
+/// and parked somewhere (usually a [`WaitQueue`](crate::sync::WaitQueue)) so that
+/// it can be woken up later.
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
 
-pub fn init(num_app: usize) {
-    println!("init TASK_MANAGER");
-    let num_app = get_num_app();
-    println!("num_app = {}", num_app);
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
 
-    let mut tasks: Vec<TaskControlBlock> = Vec::new();
-    for i in 0..num_app {
-        //todo!()
-        tasks.push(TaskControlBlock::new(get_app_data(i), i));
-    }
-    let man = TaskManager {
-        num_app,
-        inner: unsafe {
-            SpinMutex::new(TaskManagerInner {
-                tasks,
-                current_task: 0,
-            })
-        },
-    };
-
-    TASK_MANAGER.call_once(|| man);
+    drop(task);
+    schedule(task_cx_ptr);
 }
 
-impl TaskManager {
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.lock();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
-        }
-        panic!("unreachable in run_first_task!");
-    }
+/// Mark the current task as `Blocked`, before it is parked on a wait queue.
+pub fn mark_current_blocked() -> Arc<TaskControlBlock> {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Blocked;
+    TASK_MANAGER.inc_blocked();
+    drop(task_inner);
+    task
+}
 
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.lock();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Ready;
-    }
+/// Like [`mark_current_blocked`], handing the task to `arm` to set up its
+/// wakeup first. It only counts as blocked once that is done, so an idle hart
+/// never finds it blocked with nothing left to wake it up.
+pub fn mark_current_blocked_with(arm: impl FnOnce(Arc<TaskControlBlock>)) {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Blocked;
+    // a wakeup coming meanwhile waits for `task_inner`
+    arm(Arc::clone(&task));
+    TASK_MANAGER.inc_blocked();
+}
 
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.lock();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Exited;
+/// Make a blocked task runnable again. Returns false if it was not blocked,
+/// e.g. because it has been killed meanwhile.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) -> bool {
+    let mut task_inner = task.inner_exclusive_access();
+    // a killed task stays dead
+    if task_inner.task_status != TaskStatus::Blocked {
+        return false;
     }
+    task_inner.task_status = TaskStatus::Ready;
+    TASK_MANAGER.dec_blocked();
+    drop(task_inner);
 
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.lock();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
-    }
+    add_task(task);
+    true
+}
 
-    fn get_current_token(&self) -> usize {
-        let inner = self.inner.lock();
-        inner.tasks[inner.current_task].get_user_token()
-    }
+/// Exit the current 'Running' thread and run the next task in task list.
+///
+/// When the main thread exits, the whole process goes with it: threads that
+/// are not running are killed right away, those running on other harts exit
+/// on their next trap. The last thread to leave tears the process down.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let task = take_current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
 
-    /// Get the current 'Running' task's trap contexts.
-    fn get_current_trap_cx(&self) -> &'static mut TrapContext {
-        let inner = self.inner.lock();
-        inner.tasks[inner.current_task].get_trap_cx()
+    let mut process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let res = task_inner.res.as_ref().unwrap();
+    let tid = res.tid;
+    // Release the user stack and trap context of this thread before anyone
+    // can reap it. The tid stays taken until then, so that it cannot name a
+    // newer thread while `sys_waittid` may still look it up.
+    res.dealloc_user_res(&mut process_inner);
+    task_inner.task_status = TaskStatus::Exited;
+    task_inner.exit_code = Some(exit_code);
+    task_inner.join_queue.wake_all();
+    drop(task_inner);
+    drop(process_inner);
+
+    TASK_MANAGER.remove(&task);
+
+    let mut process_inner = process.inner_exclusive_access();
+    if tid == 0 {
+        process_inner.is_zombie = true;
+        process_inner.exit_code = exit_code;
     }
 
-    // Change the current 'Running' task's program break
-    // pub fn change_current_program_brk(&self, size: i32) -> Option<usize> {
-    //     let mut inner = self.inner.lock();
-    //     let cur = inner.current_task;
-    //     inner.tasks[cur].change_program_brk(size)
-    // }
-
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.lock();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
+    let mut recycle_res = Vec::new();
+    let mut killed = Vec::new();
+    let mut alive = false;
+    if process_inner.is_zombie {
+        // kill the other threads, their user resources are released below
+        for task in process_inner.tasks.iter().flatten() {
+            let mut task_inner = task.inner_exclusive_access();
+            match task_inner.task_status {
+                TaskStatus::Exited => {}
+                // it exits by itself once it traps into the kernel
+                TaskStatus::Running => {
+                    alive = true;
+                    continue;
+                }
+                TaskStatus::Blocked => {
+                    TASK_MANAGER.dec_blocked();
+                    killed.push(Arc::clone(task));
+                }
+                TaskStatus::Ready => killed.push(Arc::clone(task)),
+            }
+            task_inner.task_status = TaskStatus::Exited;
+            if let Some(res) = task_inner.res.take() {
+                recycle_res.push(res);
             }
-            // go back to user mode
-        } else {
-            println!("All applications completed!");
-            shutdown(false);
         }
     }
-}
 
-pub fn run_first_task() {
-    TASK_MANAGER.get().unwrap().run_first_task();
-}
+    // only the first of the last threads to exit sees a non-empty `tasks`
+    let teardown = process_inner.is_zombie && !alive && !process_inner.tasks.is_empty();
+    if teardown {
+        process_inner.memory_set.clear();
+        process_inner.fd_table.clear();
+        process_inner.mutex_list.clear();
+        process_inner.semaphore_list.clear();
+        process_inner.condvar_list.clear();
+        // the current thread is kept alive below until we are off its kernel stack
+        process_inner.tasks.clear();
+    }
+    drop(process_inner);
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.get().unwrap().run_next_task();
-}
+    // `TaskUserRes::drop` locks the process again
+    drop(recycle_res);
+    for task in killed.iter() {
+        TASK_MANAGER.remove(task);
+    }
+    drop(killed);
 
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.get().unwrap().mark_current_suspended();
-}
+    if teardown {
+        remove_from_pid2process(process.getpid());
+    }
+    drop(process);
+
+    set_exited_task(task);
 
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.get().unwrap().mark_current_exited();
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
 }
 
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
+/// Thread id of the current task inside its process.
+pub fn current_tid() -> usize {
+    let task = current_task().unwrap();
+    let tid = task.inner_exclusive_access().res.as_ref().unwrap().tid;
+    tid
 }
 
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
+/// Resolve a page fault of the current process at `addr` for an `access`
+/// (`Read`, `Write` or `Execute`). Fails if the access is not allowed there.
+pub fn handle_page_fault(addr: usize, access: PageTableFlags) -> Result<(), Error> {
+    // not under the process lock: its own pages may be swapped out to make room
+    let frame = VirtMemAllocOption::new(1).set_uninit(true).alloc_single()?;
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner
+        .memory_set
+        .handle_page_fault(VirtAddr::from(addr), access, frame)
 }
 
-/// Get the current 'Running' task's token.
-pub fn current_user_token() -> usize {
-    TASK_MANAGER.get().unwrap().get_current_token()
+/// The `len` bytes of the current process at `ptr`, faulted in for an `access`
+/// (`Read` or `Write`) and pinned so that they stay in memory while the kernel
+/// works on them.
+pub fn current_user_buffer(
+    ptr: *const u8,
+    len: usize,
+    access: PageTableFlags,
+) -> Result<UserBuffer, Error> {
+    let mut start = ptr as usize;
+    let end = start.checked_add(len).ok_or(Error::PageFault)?;
+    let mut buffers = Vec::new();
+    let mut pins = Vec::new();
+    while start < end {
+        let page = start & !(PAGE_SIZE - 1);
+        let process = current_process();
+        let pinned = process
+            .inner_exclusive_access()
+            .memory_set
+            .pin_user_page(VirtAddr::from(page), access)?;
+        drop(process);
+        let Some(frame) = pinned else {
+            handle_page_fault(page, access)?;
+            continue;
+        };
+        let page_end = (page + PAGE_SIZE).min(end);
+        let bytes = unsafe { core::slice::from_raw_parts_mut(frame.as_mut_ptr(), PAGE_SIZE) };
+        buffers.push(&mut bytes[start - page..page_end - page]);
+        pins.push(frame);
+        start = page_end;
+    }
+    Ok(UserBuffer::with_pins(buffers, pins))
 }
 
-/// Get the current 'Running' task's trap contexts.
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    TASK_MANAGER.get().unwrap().get_current_trap_cx()
+/// Copy `value` to `ptr` in the current process.
+pub fn copy_to_current_user<T: Copy>(ptr: *mut T, value: &T) -> Result<(), Error> {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    let mut buf = current_user_buffer(ptr as *const u8, bytes.len(), PageTableFlags::Write)?;
+    let mut copied = 0;
+    for buffer in buf.buffers.iter_mut() {
+        buffer.copy_from_slice(&bytes[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    Ok(())
 }
 
-// Change the current 'Running' task's program break
-// pub fn change_program_brk(size: i32) -> Option<usize> {
-//     TASK_MANAGER.get().unwrap().change_current_program_brk(size)
-// }
+/// Copy a `T` from `ptr` in the current process.
+pub fn copy_from_current_user<T: Copy>(ptr: *const T) -> Result<T, Error> {
+    let buf = current_user_buffer(ptr as *const u8, size_of::<T>(), PageTableFlags::Read)?;
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let mut copied = 0;
+    for buffer in buf.buffers.iter() {
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buffer.as_ptr(),
+                (value.as_mut_ptr() as *mut u8).add(copied),
+                buffer.len(),
+            )
+        };
+        copied += buffer.len();
+    }
+    Ok(unsafe { value.assume_init() })
+}
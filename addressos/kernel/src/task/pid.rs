@@ -0,0 +1,238 @@
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::mutex::SpinMutex;
+
+use crate::{
+    arch::mm::PageTableFlags,
+    config::{
+        kernel_stack_position, trap_cx_bottom_from_tid, ustack_bottom_from_tid, PAGE_SIZE,
+        USER_STACK_SIZE,
+    },
+    error::Error,
+    mm::{
+        address::{PhysPageNum, VirtAddr},
+        memory_set::{MapArea, MapType, KERNEL_SPACE},
+        option::VirtMemAllocOption,
+        page_table::{PageTableEntryTrait, PageTableFlagsTrait},
+    },
+};
+
+use super::process::{ProcessControlBlock, ProcessControlBlockInner};
+
+pub(crate) struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    pub(crate) const fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+
+    pub(crate) fn alloc(&mut self) -> usize {
+        self.recycled.pop().unwrap_or_else(|| {
+            self.current += 1;
+            self.current - 1
+        })
+    }
+
+    pub(crate) fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.contains(&id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+static PID_ALLOCATOR: SpinMutex<RecycleAllocator> = SpinMutex::new(RecycleAllocator::new());
+
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.lock().dealloc(self.0);
+    }
+}
+
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.lock().alloc())
+}
+
+static KSTACK_ALLOCATOR: SpinMutex<RecycleAllocator> = SpinMutex::new(RecycleAllocator::new());
+
+/// Kernel stack of a thread, mapped into `KERNEL_SPACE` below the trampoline.
+pub struct KernelStack {
+    id: usize,
+}
+
+pub fn kstack_alloc() -> KernelStack {
+    let id = KSTACK_ALLOCATOR.lock().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(id);
+
+    let stack_area = MapArea::new_with_frames(
+        kernel_stack_bottom.into(),
+        kernel_stack_top - kernel_stack_bottom,
+        PageTableFlags::new()
+            .set_readable(true)
+            .set_writable(true)
+            .set_valid(true),
+        MapType::Framed,
+        VirtMemAllocOption::new((kernel_stack_top - kernel_stack_bottom) / PAGE_SIZE)
+            .alloc()
+            .unwrap(),
+    );
+
+    KERNEL_SPACE.get().unwrap().lock().map(stack_area);
+
+    KernelStack { id }
+}
+
+impl KernelStack {
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.id);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.id);
+        KERNEL_SPACE
+            .get()
+            .unwrap()
+            .lock()
+            .unmap(VirtAddr::from(kernel_stack_bottom))
+            .unwrap();
+        KSTACK_ALLOCATOR.lock().dealloc(self.id);
+    }
+}
+
+/// Per-thread resources living in the user address space of the process:
+/// a user stack and a trap context page, both placed according to `tid`.
+pub struct TaskUserRes {
+    pub tid: usize,
+    pub ustack_base: usize,
+    pub process: Weak<ProcessControlBlock>,
+}
+
+impl TaskUserRes {
+    /// Take a tid in `process`, and map the user stack and trap context page
+    /// of that tid if `alloc_user_res`. Fails, leaving `process` as it was, if
+    /// there is no memory or a mapping of the process is in the way.
+    pub fn new(
+        process: Arc<ProcessControlBlock>,
+        ustack_base: usize,
+        alloc_user_res: bool,
+    ) -> Result<Self, Error> {
+        let tid = process.inner_exclusive_access().alloc_tid();
+        if alloc_user_res {
+            if let Err(error) = Self::alloc_user_res(&process, ustack_base, tid) {
+                process.inner_exclusive_access().dealloc_tid(tid);
+                return Err(error);
+            }
+        }
+        Ok(Self {
+            tid,
+            ustack_base,
+            process: Arc::downgrade(&process),
+        })
+    }
+
+    fn alloc_user_res(
+        process: &ProcessControlBlock,
+        ustack_base: usize,
+        tid: usize,
+    ) -> Result<(), Error> {
+        let mut process_inner = process.inner_exclusive_access();
+        let ustack_bottom = ustack_bottom_from_tid(ustack_base, tid);
+        let trap_cx_bottom = trap_cx_bottom_from_tid(tid);
+        // `sys_mmap` may have put something there
+        let taken = (ustack_bottom..ustack_bottom + USER_STACK_SIZE)
+            .step_by(PAGE_SIZE)
+            .chain([trap_cx_bottom])
+            .any(|va| process_inner.memory_set.is_mapped(VirtAddr::from(va)));
+        if taken {
+            return Err(Error::NotEnoughResources);
+        }
+        let ustack_frames = VirtMemAllocOption::new(USER_STACK_SIZE / PAGE_SIZE).alloc()?;
+        let trap_cx_frames = VirtMemAllocOption::new(1).alloc()?;
+
+        let user_stack_area = MapArea::new_with_frames(
+            ustack_bottom.into(),
+            USER_STACK_SIZE,
+            PageTableFlags::new()
+                .set_accessible_by_user(true)
+                .set_readable(true)
+                .set_writable(true)
+                .set_valid(true),
+            MapType::Framed,
+            ustack_frames,
+        );
+        process_inner.memory_set.map(user_stack_area);
+
+        let trap_cx_area = MapArea::new_with_frames(
+            trap_cx_bottom.into(),
+            PAGE_SIZE,
+            PageTableFlags::new()
+                .set_readable(true)
+                .set_writable(true)
+                .set_valid(true),
+            MapType::Framed,
+            trap_cx_frames,
+        );
+        process_inner.memory_set.map(trap_cx_area);
+        Ok(())
+    }
+
+    /// Unmap the user stack and the trap context page. The tid stays taken
+    /// until `self` is dropped.
+    pub fn dealloc_user_res(&self, process_inner: &mut ProcessControlBlockInner) {
+        // already gone if the thread has exited or the process has been cleared
+        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
+        let _ = process_inner
+            .memory_set
+            .unmap(VirtAddr::from(ustack_bottom));
+        let _ = process_inner
+            .memory_set
+            .unmap(VirtAddr::from(trap_cx_bottom_from_tid(self.tid)));
+    }
+
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner
+            .memory_set
+            .pt
+            .translate(VirtAddr::from(self.trap_cx_user_va()))
+            .unwrap()
+            .phys_page_num()
+    }
+
+    pub fn ustack_top(&self) -> usize {
+        ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        // nothing to give back once the whole process is gone
+        let Some(process) = self.process.upgrade() else {
+            return;
+        };
+        let mut process_inner = process.inner_exclusive_access();
+        self.dealloc_user_res(&mut process_inner);
+        process_inner.dealloc_tid(self.tid);
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::{sync::Arc, vec::Vec};
+use spin::mutex::{SpinMutex, SpinMutexGuard};
+
+use crate::{
+    fs::{File, Stdin, Stdout},
+    mm::memory_set::{MemorySet, KERNEL_SPACE},
+    sync::{Condvar, DeadlockDetector, Mutex, Semaphore},
+    trap::{context::TrapContext, trap_handler},
+};
+
+use super::{
+    manager::{insert_into_pid2process, TASK_MANAGER},
+    pid::{pid_alloc, PidHandle, RecycleAllocator},
+    task::TaskControlBlock,
+};
+
+/// A process owns the address space and the file descriptors. It runs as one
+/// or more threads, each being a [`TaskControlBlock`].
+pub struct ProcessControlBlock {
+    pub pid: PidHandle,
+    inner: SpinMutex<ProcessControlBlockInner>,
+}
+
+pub struct ProcessControlBlockInner {
+    pub is_zombie: bool,
+    pub memory_set: MemorySet,
+    pub base_size: usize,
+    pub heap_bottom: usize,
+    pub program_brk: usize,
+    pub exit_code: i32,
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+    /// Threads indexed by tid. Thread 0 is the main thread.
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    pub task_res_allocator: RecycleAllocator,
+    pub mutex_list: Vec<Option<Arc<Mutex>>>,
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    pub deadlock_detect: bool,
+    pub mutex_detector: DeadlockDetector,
+    pub semaphore_detector: DeadlockDetector,
+}
+
+impl ProcessControlBlockInner {
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn get_task(&self, tid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.tasks.get(tid).cloned().flatten()
+    }
+}
+
+impl ProcessControlBlock {
+    pub fn inner_exclusive_access(&self) -> SpinMutexGuard<'_, ProcessControlBlockInner> {
+        self.inner.lock()
+    }
+
+    pub fn try_inner_exclusive_access(
+        &self,
+    ) -> Option<SpinMutexGuard<'_, ProcessControlBlockInner>> {
+        self.inner.try_lock()
+    }
+
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+
+    /// Create a process from an ELF image and make its main thread runnable.
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let process = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: SpinMutex::new(ProcessControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                base_size: ustack_base,
+                heap_bottom: ustack_base,
+                program_brk: ustack_base,
+                exit_code: 0,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                mutex_detector: DeadlockDetector::new(),
+                semaphore_detector: DeadlockDetector::new(),
+            }),
+        });
+
+        // create the main thread
+        let task = Arc::new(
+            TaskControlBlock::new(Arc::clone(&process), ustack_base, true)
+                .expect("no memory for the main thread"),
+        );
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        drop(task_inner);
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.get().unwrap().lock().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+
+        process
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        TASK_MANAGER.spawn(task);
+        process
+    }
+}
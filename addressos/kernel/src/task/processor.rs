@@ -0,0 +1,164 @@
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+use log::info;
+
+use crate::{
+    arch::{
+        cpu::{clear_ipi, wait_for_interrupt},
+        power::shutdown,
+    },
+    cpu::this_cpu,
+    drivers::{handle_irq, waiting_for_irq},
+    mm,
+    timer::{expire_timers, has_timers, start_slice, stop_slice},
+    trap::context::TrapContext,
+};
+
+use super::{
+    context::TaskContext,
+    manager::{fetch_task, TASK_MANAGER},
+    process::ProcessControlBlock,
+    switch::__switch,
+    task::{TaskControlBlock, TaskStatus},
+};
+
+/// Scheduling state of one hart, found in its [`Cpu`](crate::cpu::Cpu) area.
+pub struct Processor {
+    current: Option<Arc<TaskControlBlock>>,
+    /// A thread that exited on its own kernel stack. It is dropped from the idle
+    /// loop, once nothing runs on that stack any more.
+    exited: Option<Arc<TaskControlBlock>>,
+    idle_task_cx: TaskContext,
+}
+
+impl Processor {
+    pub const fn new() -> Self {
+        Self {
+            current: None,
+            exited: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+/// The idle loop of a hart: keep picking a ready task and switch to it.
+pub fn run_tasks() -> ! {
+    loop {
+        let mut processor = this_cpu().processor.lock();
+        let exited = processor.exited.take();
+        drop(processor);
+        drop(exited);
+
+        if let Some(task) = fetch_task() {
+            let mut task_inner = task.inner_exclusive_access();
+            // killed by another thread of its process while waiting in the queue
+            if task_inner.task_status == TaskStatus::Exited {
+                continue;
+            }
+            task_inner.task_status = TaskStatus::Running;
+            drop(task_inner);
+
+            // the hart it last ran on may not have saved its context yet
+            while task.on_cpu.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::Relaxed);
+
+            let next_task_cx_ptr = &task.inner_exclusive_access().task_cx as *const TaskContext;
+            let mut processor = this_cpu().processor.lock();
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            processor.current = Some(Arc::clone(&task));
+            drop(processor);
+            start_slice();
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+            // back on the idle stack, the context of `task` is saved now
+            task.on_cpu.store(false, Ordering::Release);
+        } else if TASK_MANAGER.task_count() == 0 {
+            println!("All applications completed!");
+            info!("[kernel] {}", mm::stats());
+            shutdown(false);
+        } else if TASK_MANAGER.blocked_count() == TASK_MANAGER.task_count()
+            && !waiting_for_irq()
+            && !has_timers()
+        {
+            println!("[kernel] All remaining tasks are blocked, deadlock!");
+            shutdown(true);
+        } else {
+            // Nothing to run: stop the tick and sleep until a device or a timer
+            // interrupts, or another hart has a task for us. Interrupts are only
+            // taken from user mode, so they are served here.
+            stop_slice();
+            TASK_MANAGER.set_idle(true);
+            if !TASK_MANAGER.has_ready() {
+                wait_for_interrupt();
+            }
+            TASK_MANAGER.set_idle(false);
+            clear_ipi();
+            handle_irq();
+            expire_timers();
+        }
+    }
+}
+
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    this_cpu().processor.lock().take_current()
+}
+
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    this_cpu().processor.lock().current()
+}
+
+/// Hand an exited task over to the idle loop, which drops it after switching away.
+pub fn set_exited_task(task: Arc<TaskControlBlock>) {
+    this_cpu().processor.lock().exited = Some(task);
+}
+
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process.upgrade().unwrap()
+}
+
+/// Get the current 'Running' task's token.
+pub fn current_user_token() -> usize {
+    current_task().unwrap().get_user_token()
+}
+
+/// Get the current 'Running' task's trap contexts.
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    let task = current_task().unwrap();
+    let trap_cx = task.inner_exclusive_access().get_trap_cx();
+    trap_cx
+}
+
+/// Where the current 'Running' task's trap context lives in user space.
+pub fn current_trap_cx_user_va() -> usize {
+    let task = current_task().unwrap();
+    let trap_cx_user_va = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .trap_cx_user_va();
+    trap_cx_user_va
+}
+
+/// Return to the idle loop, saving the running context into `switched_task_cx_ptr`.
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let idle_task_cx_ptr = this_cpu().processor.lock().get_idle_task_cx_ptr();
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
@@ -1,108 +1,82 @@
-use crate::{
-    arch::mm::PageTableFlags,
-    config::{kernel_stack_position, PAGE_SIZE, TRAP_CONTEXT},
-    mm::{
-        address::{PhysPageNum, VirtAddr},
-        memory_set::{MapArea, MapType, MemorySet, KERNEL_SPACE},
-        option::VirtMemAllocOption,
-        page_table::{PageTableEntryTrait, PageTableFlagsTrait},
-    },
-    trap::{context::TrapContext, trap_handler},
-};
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::AtomicBool;
+use spin::mutex::{SpinMutex, SpinMutexGuard};
+
+use crate::{error::Error, mm::address::PhysPageNum, sync::WaitQueue, trap::context::TrapContext};
 
-use super::context::TaskContext;
+use super::{
+    context::TaskContext,
+    pid::{kstack_alloc, KernelStack, TaskUserRes},
+    process::ProcessControlBlock,
+};
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum TaskStatus {
     Ready,
     Running,
+    Blocked,
     Exited,
 }
 
+/// A thread of a [`ProcessControlBlock`].
 pub struct TaskControlBlock {
-    pub task_status: TaskStatus,
-    pub task_cx: TaskContext,
-    pub memory_set: MemorySet,
+    pub process: Weak<ProcessControlBlock>,
+    pub kernel_stack: KernelStack,
+    /// Set while a hart runs on the kernel stack of this thread. Another hart
+    /// must wait for it to clear before switching to the saved `task_cx`.
+    pub on_cpu: AtomicBool,
+    inner: SpinMutex<TaskControlBlockInner>,
+}
+
+pub struct TaskControlBlockInner {
+    pub res: Option<TaskUserRes>,
     pub trap_cx_ppn: PhysPageNum,
-    pub base_size: usize,
-    pub heap_bottom: usize,
-    pub program_brk: usize,
+    pub task_cx: TaskContext,
+    pub task_status: TaskStatus,
+    pub exit_code: Option<i32>,
+    /// Threads blocked in `sys_waittid` on this one.
+    pub join_queue: WaitQueue,
 }
 
-impl TaskControlBlock {
+impl TaskControlBlockInner {
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-    pub fn new(elf_data: &[u8], app_id: usize) -> Self {
-        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .pt
-            .translate(VirtAddr::from(TRAP_CONTEXT))
-            .unwrap()
-            .phys_page_num();
-        let task_status = TaskStatus::Ready;
-
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(app_id);
-
-        let stack_area = MapArea::new_with_frames(
-            kernel_stack_bottom.into(),
-            kernel_stack_top - kernel_stack_bottom,
-            PageTableFlags::new()
-                .set_readable(true)
-                .set_writable(true)
-                .set_valid(true),
-            MapType::Framed,
-            VirtMemAllocOption::new((kernel_stack_top - kernel_stack_bottom) / PAGE_SIZE)
-                .alloc()
-                .unwrap(),
-        );
+}
 
-        let kernel_space = KERNEL_SPACE.get().unwrap();
+impl TaskControlBlock {
+    pub fn inner_exclusive_access(&self) -> SpinMutexGuard<'_, TaskControlBlockInner> {
+        self.inner.lock()
+    }
 
-        kernel_space.lock().map(stack_area);
+    pub fn get_user_token(&self) -> usize {
+        let process = self.process.upgrade().unwrap();
+        let token = process.inner_exclusive_access().get_user_token();
+        token
+    }
 
-        let task_control_block = Self {
-            task_status,
-            task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-            memory_set,
-            trap_cx_ppn,
-            base_size: user_sp,
-            heap_bottom: user_sp,
-            program_brk: user_sp,
-        };
-        // // prepare TrapContext in user space
-        let trap_cx = task_control_block.get_trap_cx();
-        let satp = 8usize << 60 | kernel_space.lock().pt.get_root_paddr().floor().0;
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            satp,
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
+    /// A new thread of `process`. Fails if its user resources cannot be set up.
+    pub fn new(
+        process: Arc<ProcessControlBlock>,
+        ustack_base: usize,
+        alloc_user_res: bool,
+    ) -> Result<Self, Error> {
+        let res = TaskUserRes::new(Arc::clone(&process), ustack_base, alloc_user_res)?;
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kernel_stack = kstack_alloc();
+        let kernel_stack_top = kernel_stack.get_top();
+        Ok(Self {
+            process: Arc::downgrade(&process),
+            kernel_stack,
+            on_cpu: AtomicBool::new(false),
+            inner: SpinMutex::new(TaskControlBlockInner {
+                res: Some(res),
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                join_queue: WaitQueue::new(),
+            }),
+        })
     }
-    // pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
-    //     let old_break = self.program_brk;
-    //     let new_brk = self.program_brk as isize + size as isize;
-    //     if new_brk < self.heap_bottom as isize {
-    //         return None;
-    //     }
-    //     let result = if size < 0 {
-    //         self.memory_set
-    //             .shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
-    //     } else {
-    //         self.memory_set
-    //             .append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
-    //     };
-    //     if result {
-    //         self.program_brk = new_brk as usize;
-    //         Some(old_break)
-    //     } else {
-    //         None
-    //     }
-    // }
 }
@@ -1,19 +1,219 @@
+//! Time, and the timer of each hart.
+//!
+//! There is no periodic tick. A hart programs its timer for the earliest of
+//! the end of the time slice of the task it runs and the first pending timer,
+//! and for nothing at all when it has no task.
+
+use alloc::{boxed::Box, collections::BinaryHeap};
+use core::{
+    cmp::Ordering as CmpOrdering,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use log::info;
 use riscv::register::time;
 use sbi_rt::set_timer;
+use spin::mutex::SpinMutex;
 
-use crate::config::CLOCK_FREQ;
+use crate::{
+    config::CLOCK_FREQ,
+    cpu::this_cpu,
+    fdt::fdt,
+    task::{block_current_and_run_next, mark_current_blocked_with, wakeup_task},
+};
 
+/// Time slices per second.
 const TICKS_PER_SEC: usize = 100;
-const MSEC_PER_SEC: usize = 1000;
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// Ticks of `time` per second.
+static TIMEBASE_FREQ: AtomicUsize = AtomicUsize::new(CLOCK_FREQ);
+
+/// Nanoseconds from the epoch to when `time` was 0, once a clock says.
+static BOOT_TIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Take the frequency of `time` from the device tree.
+pub fn init() {
+    let freq = fdt().find_node("/cpus").and_then(|cpus| {
+        cpus.property_usize("timebase-frequency").or_else(|| {
+            cpus.children()
+                .find_map(|cpu| cpu.property_usize("timebase-frequency"))
+        })
+    });
+    if let Some(freq) = freq.filter(|&freq| freq > 0) {
+        TIMEBASE_FREQ.store(freq, Ordering::Relaxed);
+    }
+    info!("[kernel] timebase {} Hz", timebase_freq());
+}
+
+fn timebase_freq() -> usize {
+    TIMEBASE_FREQ.load(Ordering::Relaxed)
+}
 
 pub fn get_time() -> usize {
     time::read()
 }
 
-pub fn get_time_ms() -> usize {
-    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+/// Nanoseconds since boot.
+pub fn get_time_ns() -> u64 {
+    let ticks = time::read() as u64;
+    let freq = timebase_freq() as u64;
+    ticks / freq * NSEC_PER_SEC + ticks % freq * NSEC_PER_SEC / freq
+}
+
+/// Nanoseconds since the epoch, or since boot without a clock to tell.
+pub fn realtime_ns() -> u64 {
+    BOOT_TIME_NS.load(Ordering::Relaxed) + get_time_ns()
+}
+
+/// Set the wall clock, from a real-time clock reading `now` nanoseconds
+/// since the epoch.
+pub fn set_realtime_ns(now: u64) {
+    BOOT_TIME_NS.store(now.saturating_sub(get_time_ns()), Ordering::Relaxed);
+}
+
+/// Ticks of `time` in `ns` nanoseconds, rounded up.
+fn ns_to_ticks(ns: u64) -> usize {
+    let freq = timebase_freq() as u64;
+    (ns / NSEC_PER_SEC * freq + (ns % NSEC_PER_SEC * freq).div_ceil(NSEC_PER_SEC)) as usize
+}
+
+static TIMER_MANAGER: TimerManager = TimerManager::new();
+
+/// Something to do once `time` reaches `deadline`.
+struct Timer {
+    deadline: usize,
+    /// Keeps timers of the same deadline in the order they were added.
+    seq: usize,
+    callback: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    /// The earliest timer is the greatest, at the top of the heap.
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+/// The pending timers of all harts.
+pub struct TimerManager {
+    timers: SpinMutex<BinaryHeap<Timer>>,
+    next_seq: AtomicUsize,
+}
+
+impl TimerManager {
+    const fn new() -> Self {
+        Self {
+            timers: SpinMutex::new(BinaryHeap::new()),
+            next_seq: AtomicUsize::new(0),
+        }
+    }
+
+    fn add(&self, deadline: usize, callback: Box<dyn FnOnce() + Send>) {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.timers.lock().push(Timer {
+            deadline,
+            seq,
+            callback,
+        });
+    }
+
+    fn next_deadline(&self) -> Option<usize> {
+        self.timers.lock().peek().map(|timer| timer.deadline)
+    }
+
+    /// Run the timers that are due.
+    fn expire(&self) {
+        loop {
+            let mut timers = self.timers.lock();
+            let due = timers
+                .peek()
+                .is_some_and(|timer| timer.deadline <= get_time());
+            if !due {
+                break;
+            }
+            let timer = timers.pop().unwrap();
+            // callbacks may add timers
+            drop(timers);
+            (timer.callback)();
+        }
+    }
+}
+
+/// Call `callback` once `ns` nanoseconds have passed, from whichever hart
+/// notices first.
+pub fn add_timer(ns: u64, callback: impl FnOnce() + Send + 'static) {
+    TIMER_MANAGER.add(
+        get_time().saturating_add(ns_to_ticks(ns)),
+        Box::new(callback),
+    );
+    // it may come before what this hart waits for
+    program_timer();
+}
+
+pub fn has_timers() -> bool {
+    TIMER_MANAGER.next_deadline().is_some()
 }
 
-pub fn set_next_trigger() {
-    set_timer((get_time() + CLOCK_FREQ / TICKS_PER_SEC).try_into().unwrap());
-}
\ No newline at end of file
+/// Block the current task for `ns` nanoseconds.
+pub fn sleep(ns: u64) {
+    mark_current_blocked_with(|task| {
+        add_timer(ns, move || {
+            wakeup_task(task);
+        })
+    });
+    block_current_and_run_next();
+}
+
+/// Set the timer of this hart for the end of its time slice or the first
+/// pending timer, whichever comes first.
+fn program_timer() {
+    let slice_end = this_cpu().slice_end.load(Ordering::Relaxed);
+    let deadline = TIMER_MANAGER
+        .next_deadline()
+        .map_or(slice_end, |deadline| deadline.min(slice_end));
+    // usize::MAX for no interrupt at all
+    set_timer(deadline as u64);
+}
+
+/// Give the task about to run on this hart a new time slice.
+pub fn start_slice() {
+    this_cpu().slice_end.store(
+        get_time() + timebase_freq() / TICKS_PER_SEC,
+        Ordering::Relaxed,
+    );
+    program_timer();
+}
+
+/// Stop the time slices of this hart, which has nothing to run.
+pub fn stop_slice() {
+    this_cpu().slice_end.store(usize::MAX, Ordering::Relaxed);
+    program_timer();
+}
+
+/// Run the timers that are due, and set the timer for what comes next.
+pub fn expire_timers() {
+    TIMER_MANAGER.expire();
+    program_timer();
+}
+
+/// Serve a timer interrupt. Returns whether the time slice of the task
+/// running on this hart is over.
+pub fn handle_timer() -> bool {
+    expire_timers();
+    get_time() >= this_cpu().slice_end.load(Ordering::Relaxed)
+}
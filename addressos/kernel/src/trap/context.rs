@@ -17,6 +17,8 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// Addr of trap_handler function
     pub trap_handler: usize,
+    /// Per-CPU pointer of the hart the task last returned to user mode on
+    pub kernel_tp: usize,
 }
 
 impl TrapContext {
@@ -32,8 +34,8 @@ impl TrapContext {
         kernel_sp: usize,
         trap_handler: usize,
     ) -> Self {
-        let sstatus = sstatus::read(); // CSR sstatus
         unsafe { set_spp(SPP::User) }; //previous privilege mode: user mode
+        let sstatus = sstatus::read(); // CSR sstatus
         let mut cx = Self {
             x: [0; 32],
             sstatus,
@@ -41,6 +43,7 @@ impl TrapContext {
             kernel_satp,  // addr of page table
             kernel_sp,    // kernel stack
             trap_handler, // addr of trap_handler function
+            kernel_tp: 0, // filled in by `__restore`
         };
         cx.set_sp(sp); // app's user stack pointer
         cx // return initial Trap Context of app
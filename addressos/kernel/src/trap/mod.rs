@@ -7,8 +7,16 @@ use riscv::register::{
 };
 
 use crate::{
-    config::{TRAMPOLINE, TRAP_CONTEXT},
-    ffi::__alltraps, task::{current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next}, timer::set_next_trigger,
+    arch::{cpu::clear_ipi, mm::PageTableFlags},
+    config::TRAMPOLINE,
+    drivers::handle_irq,
+    ffi::__alltraps,
+    syscall::syscall,
+    task::{
+        current_process, current_trap_cx, current_trap_cx_user_va, exit_current_and_run_next,
+        handle_page_fault, suspend_current_and_run_next,
+    },
+    timer::handle_timer,
 };
 
 use self::context::TrapContext;
@@ -39,9 +47,27 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// Take the interrupts other harts send to wake this one up.
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+/// Take the interrupts of devices, which the PLIC routes to this hart.
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .deactivate();
 
     let cx = current_trap_cx();
 
@@ -50,23 +76,43 @@ pub fn trap_handler() -> ! {
 
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
-            //cx.sepc += 4;
-            //cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            cx.sepc += 4;
+            let args = [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]];
+            cx.x[10] = syscall(cx.x[17], args) as usize;
+        }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let access = match scause.cause() {
+                Trap::Exception(Exception::StorePageFault) => PageTableFlags::Write,
+                Trap::Exception(Exception::LoadPageFault) => PageTableFlags::Read,
+                _ => PageTableFlags::Execute,
+            };
+            // a page not touched yet or swapped out
+            if handle_page_fault(stval, access).is_err() {
+                println!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, cx.sepc);
+                exit_current_and_run_next(-2);
+            }
         }
-        Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
+        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::LoadFault) => {
             println!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, cx.sepc);
-            exit_current_and_run_next();
+            exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, kernel killed it.");
-            exit_current_and_run_next();
+            exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            if handle_timer() {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // meant to wake this hart from idle, which it has left already
+            clear_ipi();
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_irq();
         }
         _ => {
             panic!(
@@ -76,14 +122,20 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // the main thread may have exited the process from another hart meanwhile
+    if current_process().inner_exclusive_access().is_zombie {
+        exit_current_and_run_next(-1);
+    }
     trap_return();
 }
 
 #[no_mangle]
 pub fn trap_return() -> ! {
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
-    let user_satp = current_user_token();
+    let trap_cx_ptr = current_trap_cx_user_va();
+    let process = current_process();
+    let user_satp = process.inner_exclusive_access().memory_set.activate();
+    drop(process);
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(enable_deadlock_detect(true), 0);
+
+    let mutex_id = mutex_create() as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    // locking it again can never succeed
+    assert_eq!(mutex_lock(mutex_id), -0xdead);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+
+    let sem_id = semaphore_create(2) as usize;
+    assert_eq!(semaphore_down(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), -0xdead);
+    assert_eq!(semaphore_up(sem_id), 0);
+    assert_eq!(semaphore_down(sem_id), 0);
+
+    println!("Test deadlock OK!");
+    0
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use addressos_user::{thread, *};
+
+const THREAD_COUNT: usize = 8;
+const PER_THREAD: usize = 1000;
+
+static mut COUNTER: usize = 0;
+static mut MUTEX_ID: usize = 0;
+
+fn worker(i: usize) -> i32 {
+    for _ in 0..PER_THREAD {
+        unsafe {
+            mutex_lock(MUTEX_ID);
+            let c = addr_of!(COUNTER).read_volatile();
+            // give the timer a chance to preempt inside the critical section
+            for _ in 0..10 {
+                core::hint::spin_loop();
+            }
+            addr_of_mut!(COUNTER).write_volatile(c + 1);
+            mutex_unlock(MUTEX_ID);
+        }
+    }
+    i as i32
+}
+
+fn exit_with(code: usize) -> i32 {
+    code as i32
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(gettid(), 0);
+    unsafe {
+        MUTEX_ID = mutex_create() as usize;
+        // nobody holds it yet
+        assert_eq!(mutex_unlock(MUTEX_ID), -1);
+    }
+
+    let handles: [thread::JoinHandle; THREAD_COUNT] =
+        core::array::from_fn(|i| thread::spawn(worker, i));
+    let first_tid = handles[0].tid();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join(), i as i32);
+    }
+    // a thread cannot be joined twice, nor can one join itself
+    assert_eq!(waittid(first_tid), -1);
+    assert_eq!(waittid(0), -1);
+
+    // an exited thread keeps its tid until it is joined
+    let early = thread::spawn(exit_with, 7);
+    sleep(10);
+    let late = thread::spawn(exit_with, 8);
+    assert_ne!(early.tid(), late.tid());
+    assert_eq!(early.join(), 7);
+    assert_eq!(late.join(), 8);
+
+    assert_eq!(
+        unsafe { addr_of!(COUNTER).read_volatile() },
+        THREAD_COUNT * PER_THREAD
+    );
+    println!("Test threads OK!");
+    0
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use addressos_user::{thread, *};
+
+const THREAD_COUNT: usize = 16;
+const ROUNDS: usize = 100;
+const PER_ROUND: usize = 1000;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Hammer a shared atomic, yielding now and then so threads migrate between harts.
+fn worker(_: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        for _ in 0..PER_ROUND {
+            COUNTER.fetch_add(1, Ordering::Relaxed);
+        }
+        sched_yield();
+    }
+    gettid() as i32
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let handles: [thread::JoinHandle; THREAD_COUNT] =
+        core::array::from_fn(|_| thread::spawn(worker, 0));
+    for handle in handles {
+        let tid = handle.tid();
+        assert_eq!(handle.join(), tid as i32);
+    }
+    assert_eq!(
+        COUNTER.load(Ordering::Relaxed),
+        THREAD_COUNT * ROUNDS * PER_ROUND
+    );
+    println!("Test smp OK!");
+    0
+}
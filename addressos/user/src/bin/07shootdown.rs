@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use addressos_user::{thread, *};
+
+const PAGE: usize = 0x1000_0000;
+const PAGE_SIZE: usize = 0x1000;
+
+static READING: AtomicBool = AtomicBool::new(false);
+static UNMAPPED: AtomicBool = AtomicBool::new(false);
+
+/// Keep the page hot in the TLB of whatever hart runs us, then touch it once
+/// more after it is gone. That last access must fault.
+fn reader(_: usize) -> i32 {
+    let page = PAGE as *const usize;
+    loop {
+        if UNMAPPED.load(Ordering::Acquire) {
+            unsafe { page.read_volatile() };
+            // a stale TLB entry let us through
+            return 1;
+        }
+        assert_eq!(unsafe { page.read_volatile() }, 0xdead_beef);
+        READING.store(true, Ordering::Release);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(mmap(PAGE, PAGE_SIZE, 0b011), 0);
+    unsafe { (PAGE as *mut usize).write_volatile(0xdead_beef) };
+
+    let handle = thread::spawn(reader, 0);
+    while !READING.load(Ordering::Acquire) {
+        sched_yield();
+    }
+    assert_eq!(munmap(PAGE, PAGE_SIZE), 0);
+    UNMAPPED.store(true, Ordering::Release);
+
+    // killed by the page fault
+    assert_eq!(handle.join(), -2);
+    println!("Test shootdown OK!");
+    0
+}
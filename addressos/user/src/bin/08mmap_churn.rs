@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+const BASE: usize = 0x1_0000_0000;
+const PAGE_SIZE: usize = 0x1000;
+/// Every page lands in a 2 MiB region of its own, so it needs a fresh level-1
+/// table. Without reclaiming them, this is more page tables than memory.
+const STRIDE: usize = 0x20_0000;
+const ROUNDS: usize = 20000;
+
+#[no_mangle]
+fn main() -> i32 {
+    for i in 0..ROUNDS {
+        let page = BASE + i * STRIDE;
+        assert_eq!(mmap(page, PAGE_SIZE, 0b011), 0);
+        unsafe { (page as *mut usize).write_volatile(i) };
+        assert_eq!(unsafe { (page as *const usize).read_volatile() }, i);
+        assert_eq!(munmap(page, PAGE_SIZE), 0);
+    }
+    println!("Test mmap churn OK!");
+    0
+}
@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+const BASE: usize = 0x2_0000_0000;
+const PAGE_SIZE: usize = 0x1000;
+/// More than the frames left once the kernel and the swap RAM disk are
+/// carved out of the 128 MiB of the machine.
+const LEN: usize = 96 * 1024 * 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(mmap(BASE, LEN, 0b011), 0);
+    for page in (BASE..BASE + LEN).step_by(PAGE_SIZE) {
+        unsafe { (page as *mut usize).write_volatile(page) };
+    }
+    // the first pages went to the swap device long ago
+    for page in (BASE..BASE + LEN).step_by(PAGE_SIZE) {
+        assert_eq!(unsafe { (page as *const usize).read_volatile() }, page);
+    }
+    assert_eq!(munmap(BASE, LEN), 0);
+    println!("Test swap OK!");
+    0
+}
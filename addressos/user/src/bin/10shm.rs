@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+const FIRST: usize = 0x3_0000_0000;
+const SECOND: usize = 0x3_1000_0000;
+const PAGE_SIZE: usize = 0x1000;
+const SIZE: usize = 4 * PAGE_SIZE;
+
+#[no_mangle]
+fn main() -> i32 {
+    let handle = shm_create(SIZE);
+    assert!(handle >= 0);
+    let handle = handle as usize;
+    // a failed attach leaves the segment alone
+    assert_eq!(shm_attach(handle, FIRST + 1, 0b011), -1);
+    // the same segment twice: both views share their frames
+    assert_eq!(shm_attach(handle, FIRST, 0b011), 0);
+    assert_eq!(shm_attach(handle, SECOND, 0b011), 0);
+    for i in 0..SIZE / PAGE_SIZE {
+        unsafe { ((FIRST + i * PAGE_SIZE) as *mut usize).write_volatile(i + 1) };
+    }
+    for i in 0..SIZE / PAGE_SIZE {
+        let value = unsafe { ((SECOND + i * PAGE_SIZE) as *const usize).read_volatile() };
+        assert_eq!(value, i + 1);
+    }
+    // munmap only takes back what mmap gave
+    assert_eq!(munmap(FIRST, SIZE), -1);
+    assert_eq!(shm_detach(FIRST), 0);
+    assert_eq!(
+        unsafe { (SECOND as *const usize).read_volatile() },
+        1,
+        "the segment outlives one detach"
+    );
+    assert_eq!(shm_detach(SECOND), 0);
+    // nothing is attached, yet the segment stays until destroyed
+    assert_eq!(shm_attach(handle, FIRST, 0b011), 0);
+    assert_eq!(shm_destroy(handle), 0);
+    assert_eq!(shm_destroy(handle), -1);
+    assert_eq!(shm_attach(handle, SECOND, 0b011), -1);
+    assert_eq!(
+        unsafe { (FIRST as *const usize).read_volatile() },
+        1,
+        "the segment outlives its destruction while attached"
+    );
+    assert_eq!(shm_detach(FIRST), 0);
+    println!("Test shm OK!");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+/// 2020-01-01, which the wall clock is well past.
+const Y2020: usize = 1_577_836_800;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut now), 0);
+    assert!(now.sec > Y2020, "no wall clock: {:?}", now);
+    assert!(now.nsec < 1_000_000_000);
+
+    let mut tv = TimeVal::default();
+    assert_eq!(gettimeofday(&mut tv), 0);
+    assert!(tv.sec >= now.sec && tv.sec - now.sec <= 1);
+    assert!(tv.usec < 1_000_000);
+
+    let mut start = TimeSpec::default();
+    let mut end = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut start), 0);
+    for _ in 0..10 {
+        sched_yield();
+    }
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut end), 0);
+    assert!((end.sec, end.nsec) >= (start.sec, start.nsec));
+
+    assert_eq!(clock_gettime(42, &mut now), -1);
+    println!("wall clock: {}.{:06}", tv.sec, tv.usec);
+    println!("Test time OK!");
+    0
+}
@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+const MSEC_PER_SEC: usize = 1000;
+
+#[no_mangle]
+fn main() -> i32 {
+    // short sleeps, well under the old 10 ms tick
+    for ms in [1, 2, 5, 20] {
+        let start = get_time() as usize;
+        assert_eq!(sleep(ms), 0);
+        let elapsed = get_time() as usize - start;
+        assert!(elapsed >= ms, "slept {} ms for {} ms", elapsed, ms);
+        println!("sleep({}) took {} ms", ms, elapsed);
+    }
+
+    let start = get_time() as usize;
+    assert_eq!(
+        nanosleep(&TimeSpec {
+            sec: 1,
+            nsec: 500_000_000
+        }),
+        0
+    );
+    assert!(get_time() as usize - start >= MSEC_PER_SEC * 3 / 2);
+
+    assert_eq!(
+        nanosleep(&TimeSpec {
+            sec: 0,
+            nsec: 1_000_000_000
+        }),
+        -1
+    );
+    println!("Test nanosleep OK!");
+    0
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::{thread, *};
+
+const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+const TCP_PORT: u16 = 7000;
+const UDP_PORT: u16 = 7001;
+const MESSAGE: &[u8] = b"hello through the loopback";
+
+/// Read exactly `buf.len()` bytes from the stream `fd`.
+fn recv_exact(fd: usize, buf: &mut [u8]) {
+    let mut received = 0;
+    while received < buf.len() {
+        let len = recv(fd, &mut buf[received..]);
+        assert!(len > 0, "connection closed after {} bytes", received);
+        received += len as usize;
+    }
+}
+
+fn echo_server(listener: usize) -> i32 {
+    let mut peer = SockAddrIn::default();
+    let conn = accept(listener, &mut peer);
+    assert!(conn >= 0);
+    assert_eq!(peer.addr, LOCALHOST);
+    let mut buf = [0u8; MESSAGE.len()];
+    recv_exact(conn as usize, &mut buf);
+    assert_eq!(send(conn as usize, &buf), buf.len() as isize);
+    assert_eq!(close(conn as usize), 0);
+    0
+}
+
+fn tcp_echo() {
+    let listener = socket(AF_INET, SOCK_STREAM);
+    assert!(listener >= 0);
+    assert_eq!(
+        bind(listener as usize, &SockAddrIn::new([0; 4], TCP_PORT)),
+        0
+    );
+    assert_eq!(listen(listener as usize, 1), 0);
+    let server = thread::spawn(echo_server, listener as usize);
+
+    let client = socket(AF_INET, SOCK_STREAM) as usize;
+    assert_eq!(connect(client, &SockAddrIn::new(LOCALHOST, TCP_PORT)), 0);
+    // a socket is a file too
+    assert_eq!(write(client, MESSAGE), MESSAGE.len() as isize);
+    let mut buf = [0u8; MESSAGE.len()];
+    recv_exact(client, &mut buf);
+    assert_eq!(buf, MESSAGE);
+    assert_eq!(server.join(), 0);
+    // the server closed its end
+    assert_eq!(recv(client, &mut buf), 0);
+    assert_eq!(close(client), 0);
+    assert_eq!(close(listener as usize), 0);
+
+    // nobody listens there
+    let refused = socket(AF_INET, SOCK_STREAM) as usize;
+    assert_eq!(
+        connect(refused, &SockAddrIn::new(LOCALHOST, TCP_PORT + 100)),
+        -1
+    );
+    println!("TCP echo OK");
+}
+
+fn udp_echo() {
+    let server = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(bind(server, &SockAddrIn::new(LOCALHOST, UDP_PORT)), 0);
+    let client = socket(AF_INET, SOCK_DGRAM) as usize;
+    assert_eq!(
+        sendto(client, MESSAGE, &SockAddrIn::new(LOCALHOST, UDP_PORT)),
+        MESSAGE.len() as isize
+    );
+
+    let mut buf = [0u8; 64];
+    let mut peer = SockAddrIn::default();
+    let len = recvfrom(server, &mut buf, &mut peer);
+    assert_eq!(&buf[..len as usize], MESSAGE);
+    assert_ne!(peer.port(), 0);
+    assert_eq!(sendto(server, &buf[..len as usize], &peer), len);
+
+    let mut reply = [0u8; 64];
+    let len = recv(client, &mut reply);
+    assert_eq!(&reply[..len as usize], MESSAGE);
+    assert_eq!(close(client), 0);
+    assert_eq!(close(server), 0);
+    println!("UDP echo OK");
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    tcp_echo();
+    udp_echo();
+    println!("Test socket OK!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+/// The guest address under QEMU user networking, port 5555 forwarded from
+/// the host with `make run NET=y`.
+const ADDR: [u8; 4] = [10, 0, 2, 15];
+const PORT: u16 = 5555;
+
+/// Echo what a client sends until it closes its end.
+fn serve(conn: usize) {
+    let mut buf = [0u8; 512];
+    loop {
+        let len = recv(conn, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        let mut sent = 0;
+        while sent < len {
+            let n = send(conn, &buf[sent as usize..len as usize]);
+            if n <= 0 {
+                return;
+            }
+            sent += n;
+        }
+    }
+}
+
+/// A TCP echo server, for `nc localhost 5555` on the host. It serves one
+/// client at a time until the machine is shut down.
+#[no_mangle]
+fn main() -> i32 {
+    let listener = socket(AF_INET, SOCK_STREAM) as usize;
+    if bind(listener, &SockAddrIn::new(ADDR, PORT)) != 0 {
+        println!("echo server: no network device, skipped");
+        return 0;
+    }
+    assert_eq!(listen(listener, 4), 0);
+    println!("echo server: listening on port {}", PORT);
+    loop {
+        let mut peer = SockAddrIn::default();
+        let conn = accept(listener, &mut peer);
+        if conn < 0 {
+            continue;
+        }
+        println!(
+            "echo server: {}.{}.{}.{}:{} connected",
+            peer.addr[0],
+            peer.addr[1],
+            peer.addr[2],
+            peer.addr[3],
+            peer.port()
+        );
+        serve(conn as usize);
+        close(conn as usize);
+    }
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+/// Where the echo service of 16unix_client listens.
+const STREAM_NAME: &str = "/run/echo.sock";
+const DGRAM_NAME: &str = "/run/echo.dgram";
+const CLIENT_NAME: &str = "/run/client.dgram";
+/// What the client sends before waiting for us to close.
+const TOTAL: usize = 64 * 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    let listener = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind(listener, &SockAddrUn::new(STREAM_NAME)), 0);
+    // the name is taken
+    let other = socket(AF_UNIX, SOCK_STREAM) as usize;
+    assert_eq!(bind(other, &SockAddrUn::new(STREAM_NAME)), -1);
+    assert_eq!(close(other), 0);
+    assert_eq!(listen(listener, 4), 0);
+    let dgram = socket(AF_UNIX, SOCK_DGRAM) as usize;
+    assert_eq!(bind(dgram, &SockAddrUn::new(DGRAM_NAME)), 0);
+
+    // echo one client, then hang up on it
+    let mut peer = SockAddrUn::default();
+    let conn = accept(listener, &mut peer);
+    assert!(conn >= 0);
+    assert!(peer.name().is_empty());
+    let mut buf = [0u8; 1024];
+    let mut echoed = 0;
+    while echoed < TOTAL {
+        let len = read(conn as usize, &mut buf);
+        assert!(len > 0, "connection closed after {} bytes", echoed);
+        assert_eq!(write(conn as usize, &buf[..len as usize]), len);
+        echoed += len as usize;
+    }
+    assert_eq!(close(conn as usize), 0);
+    println!("unix server: echoed {} bytes", echoed);
+
+    let mut from = SockAddrUn::default();
+    let len = recvfrom(dgram, &mut buf, &mut from);
+    assert!(len > 0);
+    assert_eq!(from.name(), CLIENT_NAME.as_bytes());
+    assert_eq!(sendto(dgram, &buf[..len as usize], &from), len);
+
+    assert_eq!(close(dgram), 0);
+    assert_eq!(close(listener), 0);
+    println!("Test unix server OK!");
+    0
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::{thread, *};
+
+/// Served by 15unix_server, a process of its own.
+const STREAM_NAME: &str = "/run/echo.sock";
+const DGRAM_NAME: &str = "/run/echo.dgram";
+const CLIENT_NAME: &str = "/run/client.dgram";
+
+/// More than the kernel buffers, so both ends block on each other.
+const TOTAL: usize = 64 * 1024;
+const CHUNK: usize = 1000;
+
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+fn writer(fd: usize) -> i32 {
+    let mut chunk = [0u8; CHUNK];
+    let mut sent = 0;
+    while sent < TOTAL {
+        let len = CHUNK.min(TOTAL - sent);
+        for (i, byte) in chunk[..len].iter_mut().enumerate() {
+            *byte = pattern(sent + i);
+        }
+        assert_eq!(send(fd, &chunk[..len]), len as isize);
+        sent += len;
+    }
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let fd = socket(AF_UNIX, SOCK_STREAM) as usize;
+    // the server may not be listening yet
+    while connect(fd, &SockAddrUn::new(STREAM_NAME)) != 0 {
+        sleep(1);
+    }
+
+    let writer = thread::spawn(writer, fd);
+    let mut buf = [0u8; 4096];
+    let mut received = 0;
+    while received < TOTAL {
+        let len = recv(fd, &mut buf);
+        assert!(len > 0, "connection closed after {} bytes", received);
+        for (i, &byte) in buf[..len as usize].iter().enumerate() {
+            assert_eq!(byte, pattern(received + i));
+        }
+        received += len as usize;
+    }
+    assert_eq!(writer.join(), 0);
+    // the server closes its end once everything is echoed
+    let mut peer = SockAddrUn::default();
+    assert_eq!(recvfrom(fd, &mut buf, &mut peer), 0);
+    assert_eq!(peer.name(), STREAM_NAME.as_bytes());
+    println!("unix stream echo OK");
+
+    let dgram = socket(AF_UNIX, SOCK_DGRAM) as usize;
+    assert_eq!(bind(dgram, &SockAddrUn::new(CLIENT_NAME)), 0);
+    assert_eq!(connect(dgram, &SockAddrUn::new(DGRAM_NAME)), 0);
+    assert_eq!(send(dgram, b"ping"), 4);
+    let mut from = SockAddrUn::default();
+    assert_eq!(recvfrom(dgram, &mut buf, &mut from), 4);
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(from.name(), DGRAM_NAME.as_bytes());
+    println!("unix datagram echo OK");
+
+    assert_eq!(close(dgram), 0);
+    assert_eq!(close(fd), 0);
+    println!("Test unix client OK!");
+    0
+}
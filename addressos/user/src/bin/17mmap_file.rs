@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+use addressos_user::*;
+
+const SHARED: usize = 0x4_0000_0000;
+const PRIVATE: usize = 0x4_1000_0000;
+const TARGET: usize = 0x4_2000_0000;
+const PAGE_SIZE: usize = 0x1000;
+const PAGES: usize = 2;
+const LEN: usize = PAGES * PAGE_SIZE;
+/// Files are read and written this much at a time, to spare the small stack.
+const CHUNK: usize = 512;
+
+fn page(start: usize, i: usize) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut((start + i * PAGE_SIZE) as *mut u8, PAGE_SIZE) }
+}
+
+/// A file of `PAGES` pages, page `i` filled with `fill[i]`.
+fn create_file(fill: [u8; PAGES]) -> usize {
+    let fd = memfd_create();
+    assert!(fd >= 0);
+    for byte in fill {
+        for _ in 0..PAGE_SIZE / CHUNK {
+            assert_eq!(write(fd as usize, &[byte; CHUNK]), CHUNK as isize);
+        }
+    }
+    fd as usize
+}
+
+/// Check that page `i` of the file `fd` is filled with `fill[i]`.
+fn check_file(fd: usize, fill: [u8; PAGES]) {
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut buf = [0u8; CHUNK];
+    for byte in fill {
+        for _ in 0..PAGE_SIZE / CHUNK {
+            assert_eq!(read(fd, &mut buf), CHUNK as isize);
+            assert!(buf.iter().all(|&b| b == byte));
+        }
+    }
+    // at the end of the file
+    assert_eq!(read(fd, &mut buf), 0);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let fd = create_file([0x11, 0x11]);
+
+    // writes through a shared mapping are read right away, and reach the
+    // file on msync and on munmap
+    assert_eq!(mmap_file(SHARED, LEN, 0b011, MAP_SHARED, fd, 0), 0);
+    assert!(page(SHARED, 1).iter().all(|&b| b == 0x11));
+    page(SHARED, 0).fill(0x22);
+    check_file(fd, [0x22, 0x11]);
+    assert_eq!(msync(SHARED, LEN), 0);
+    check_file(fd, [0x22, 0x11]);
+    page(SHARED, 1).fill(0x33);
+    assert_eq!(munmap(SHARED, LEN), 0);
+    check_file(fd, [0x22, 0x33]);
+
+    // writes to the file show in the pages cached for it
+    assert_eq!(lseek(fd, PAGE_SIZE as isize, SEEK_SET), PAGE_SIZE as isize);
+    for _ in 0..PAGE_SIZE / CHUNK {
+        assert_eq!(write(fd, &[0x55; CHUNK]), CHUNK as isize);
+    }
+    assert_eq!(
+        mmap_file(SHARED, PAGE_SIZE, 0b001, MAP_SHARED, fd, PAGE_SIZE),
+        0
+    );
+    assert!(page(SHARED, 0).iter().all(|&b| b == 0x55));
+    assert_eq!(munmap(SHARED, PAGE_SIZE), 0);
+
+    // writes through a private mapping stay in its copies
+    assert_eq!(
+        mmap_file(PRIVATE, LEN, 0b011, MAP_PRIVATE, fd, PAGE_SIZE),
+        0
+    );
+    assert!(page(PRIVATE, 0).iter().all(|&b| b == 0x55));
+    page(PRIVATE, 0).fill(0x44);
+    assert_eq!(munmap(PRIVATE, LEN), 0);
+    check_file(fd, [0x22, 0x55]);
+
+    // kernel writes into a shared mapping reach the file too, here by `read`
+    let target = create_file([0, 0]);
+    assert_eq!(mmap_file(TARGET, LEN, 0b011, MAP_SHARED, target, 0), 0);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mapped = unsafe { core::slice::from_raw_parts_mut(TARGET as *mut u8, LEN) };
+    assert_eq!(read(fd, mapped), LEN as isize);
+    assert_eq!(munmap(TARGET, LEN), 0);
+    check_file(target, [0x22, 0x55]);
+
+    // shared memory comes from shm_create
+    assert_eq!(
+        mmap_file(
+            SHARED,
+            LEN,
+            0b011,
+            MAP_SHARED | MAP_ANONYMOUS,
+            usize::MAX,
+            0
+        ),
+        -1
+    );
+
+    assert_eq!(close(target), 0);
+    assert_eq!(close(fd), 0);
+    println!("Test mmap file OK!");
+    0
+}
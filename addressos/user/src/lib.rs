@@ -2,13 +2,14 @@
 #![feature(linkage)]
 #![feature(panic_info_message)]
 
-use syscall::{sys_exit, sys_get_time, sys_write};
+use syscall::*;
 
 #[macro_use]
 pub mod console;
 mod fs;
 mod panic;
 mod syscall;
+pub mod thread;
 
 #[no_mangle]
 #[link_section = ".text.entry"]
@@ -34,10 +35,28 @@ fn clear_bss() {
     });
 }
 
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    sys_read(fd, buf.as_mut_ptr(), buf.len())
+}
+
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf.as_ptr(), buf.len())
 }
 
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// Move the offset of `fd` by `offset` from where `whence` says, and return
+/// the new offset.
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
 pub fn exit(error_code: isize) -> isize {
     sys_exit(error_code)
 }
@@ -46,6 +65,303 @@ pub fn sched_yield() -> isize {
     syscall::sys_sched_yield()
 }
 
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+/// Milliseconds since boot.
 pub fn get_time() -> isize {
-    sys_get_time()
-}
\ No newline at end of file
+    let mut ts = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut ts);
+    (ts.sec * 1000 + ts.nsec / 1_000_000) as isize
+}
+
+/// The time of `clock_id`: `CLOCK_REALTIME` since the epoch, or
+/// `CLOCK_MONOTONIC` since boot.
+pub fn clock_gettime(clock_id: usize, ts: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, ts)
+}
+
+pub fn gettimeofday(tv: &mut TimeVal) -> isize {
+    sys_gettimeofday(tv)
+}
+
+pub fn nanosleep(req: &TimeSpec) -> isize {
+    sys_nanosleep(req)
+}
+
+/// Sleep for `ms` milliseconds.
+pub fn sleep(ms: usize) -> isize {
+    nanosleep(&TimeSpec {
+        sec: ms / 1000,
+        nsec: ms % 1000 * 1_000_000,
+    })
+}
+
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Map `len` bytes of zeroed memory at `start`; `prot` is a mix of
+/// read (1), write (2) and execute (4).
+pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
+    sys_mmap(start, len, prot, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0)
+}
+
+/// Map the file `fd` from `offset` at `start`. With `MAP_SHARED` writes go
+/// to the file, with `MAP_PRIVATE` to a copy of the pages only this process sees.
+pub fn mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(start, len, prot, flags, fd, offset)
+}
+
+/// Write what was written through shared file mappings in the `len` bytes
+/// from `start` back to the files.
+pub fn msync(start: usize, len: usize) -> isize {
+    sys_msync(start, len)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+/// Create an empty file living in memory only, which can be mapped with
+/// [`mmap_file`], and return its fd.
+pub fn memfd_create() -> isize {
+    sys_memfd_create(core::ptr::null(), 0)
+}
+
+/// Create a shared memory segment of `size` bytes and return its handle.
+pub fn shm_create(size: usize) -> isize {
+    sys_shm_create(size)
+}
+
+/// Map the segment `handle` at `start`, with `prot` as for [`mmap`].
+pub fn shm_attach(handle: usize, start: usize, prot: usize) -> isize {
+    sys_shm_attach(handle, start, prot)
+}
+
+/// Unmap the segment attached at `start`; the last detach of a destroyed
+/// segment frees it.
+pub fn shm_detach(start: usize) -> isize {
+    sys_shm_detach(start)
+}
+
+/// Forbid new attachments to the segment `handle`, which is freed once no
+/// longer attached.
+pub fn shm_destroy(handle: usize) -> isize {
+    sys_shm_destroy(handle)
+}
+
+pub const AF_UNIX: usize = 1;
+pub const AF_INET: usize = 2;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+
+/// A socket address of some family, passed to the kernel as its bytes.
+pub trait SockAddr: Copy {}
+
+/// An IPv4 socket address, its port and address in network byte order.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SockAddrIn {
+    pub family: u16,
+    pub port: u16,
+    pub addr: [u8; 4],
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr,
+            zero: [0; 8],
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+impl SockAddr for SockAddrIn {}
+
+/// A Unix socket address: a name, such as a path, of up to 107 bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockAddrUn {
+    pub family: u16,
+    pub path: [u8; 108],
+}
+
+impl SockAddrUn {
+    pub fn new(name: &str) -> Self {
+        let mut path = [0; 108];
+        path[..name.len()].copy_from_slice(name.as_bytes());
+        Self {
+            family: AF_UNIX as u16,
+            path,
+        }
+    }
+
+    /// The name, empty for a socket without one.
+    pub fn name(&self) -> &[u8] {
+        let len = self.path.iter().position(|&b| b == 0).unwrap_or(108);
+        &self.path[..len]
+    }
+}
+
+impl Default for SockAddrUn {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl SockAddr for SockAddrUn {}
+
+fn addr_ptr<A: SockAddr>(addr: &A) -> *const u8 {
+    addr as *const A as *const u8
+}
+
+fn addr_len<A: SockAddr>() -> usize {
+    core::mem::size_of::<A>()
+}
+
+/// Create a socket of `domain`, `AF_INET` or `AF_UNIX`, and of `ty`,
+/// `SOCK_STREAM` or `SOCK_DGRAM`, and return its fd.
+pub fn socket(domain: usize, ty: usize) -> isize {
+    sys_socket(domain, ty, 0)
+}
+
+/// Bind `fd` to `addr`. For Internet sockets address 0.0.0.0 is every
+/// address and port 0 any port.
+pub fn bind<A: SockAddr>(fd: usize, addr: &A) -> isize {
+    sys_bind(fd, addr_ptr(addr), addr_len::<A>())
+}
+
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+
+/// Wait for a connection on `fd` and return its fd, with the peer in `addr`.
+pub fn accept<A: SockAddr>(fd: usize, addr: &mut A) -> isize {
+    let mut addrlen = addr_len::<A>() as u32;
+    sys_accept(fd, addr as *mut A as *mut u8, &mut addrlen)
+}
+
+pub fn connect<A: SockAddr>(fd: usize, addr: &A) -> isize {
+    sys_connect(fd, addr_ptr(addr), addr_len::<A>())
+}
+
+pub fn send(fd: usize, buf: &[u8]) -> isize {
+    sys_send(fd, buf.as_ptr(), buf.len(), 0, core::ptr::null(), 0)
+}
+
+pub fn sendto<A: SockAddr>(fd: usize, buf: &[u8], addr: &A) -> isize {
+    sys_send(
+        fd,
+        buf.as_ptr(),
+        buf.len(),
+        0,
+        addr_ptr(addr),
+        addr_len::<A>(),
+    )
+}
+
+pub fn recv(fd: usize, buf: &mut [u8]) -> isize {
+    sys_recv(
+        fd,
+        buf.as_mut_ptr(),
+        buf.len(),
+        0,
+        core::ptr::null_mut(),
+        core::ptr::null_mut(),
+    )
+}
+
+/// Receive into `buf` from `fd`, with the sender in `addr`.
+pub fn recvfrom<A: SockAddr>(fd: usize, buf: &mut [u8], addr: &mut A) -> isize {
+    let mut addrlen = addr_len::<A>() as u32;
+    sys_recv(
+        fd,
+        buf.as_mut_ptr(),
+        buf.len(),
+        0,
+        addr as *mut A as *mut u8,
+        &mut addrlen,
+    )
+}
+
+pub fn enable_deadlock_detect(enabled: bool) -> isize {
+    sys_enable_deadlock_detect(enabled as usize)
+}
+
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    sys_thread_create(entry, arg)
+}
+
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// Wait for thread `tid` to exit and return its exit code, or -1 if there is no such thread.
+pub fn waittid(tid: usize) -> isize {
+    sys_waittid(tid)
+}
+
+pub fn mutex_create() -> isize {
+    sys_mutex_create()
+}
+
+pub fn mutex_lock(mutex_id: usize) -> isize {
+    sys_mutex_lock(mutex_id)
+}
+
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+
+pub fn semaphore_create(res_count: usize) -> isize {
+    sys_semaphore_create(res_count)
+}
+
+pub fn semaphore_up(sem_id: usize) -> isize {
+    sys_semaphore_up(sem_id)
+}
+
+pub fn semaphore_down(sem_id: usize) -> isize {
+    sys_semaphore_down(sem_id)
+}
+
+pub fn condvar_create() -> isize {
+    sys_condvar_create()
+}
+
+pub fn condvar_signal(condvar_id: usize) -> isize {
+    sys_condvar_signal(condvar_id)
+}
+
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
@@ -2,6 +2,8 @@ use core::arch::asm;
 
 use num_enum::IntoPrimitive;
 
+use crate::{TimeSpec, TimeVal};
+
 pub(crate) fn syscall(id: usize, args: [usize; 3]) -> isize {
     let ret: isize;
     unsafe {
@@ -16,13 +18,76 @@ pub(crate) fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+/// Like [`syscall`], for the few calls taking more than three arguments.
+pub(crate) fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => ret,
+            in("a1") args[1],
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a7") id,
+        );
+    }
+    ret
+}
+
 #[derive(IntoPrimitive)]
 #[repr(usize)]
 enum Syscall {
+    Close = 57,
+    Lseek = 62,
+    Read = 63,
     Write = 64,
     Exit = 93,
+    Nanosleep = 101,
+    ClockGettime = 113,
     SchedYield = 124,
-    GetTime = 169,
+    Gettimeofday = 169,
+    ShmCreate = 194,
+    ShmDestroy = 195,
+    ShmAttach = 196,
+    ShmDetach = 197,
+    Socket = 198,
+    Bind = 200,
+    Listen = 201,
+    Accept = 202,
+    Connect = 203,
+    Send = 206,
+    Recv = 207,
+    Munmap = 215,
+    Mmap = 222,
+    Msync = 227,
+    MemfdCreate = 279,
+    EnableDeadlockDetect = 469,
+    ThreadCreate = 1000,
+    Gettid = 1001,
+    Waittid = 1002,
+    MutexCreate = 1010,
+    MutexLock = 1011,
+    MutexUnlock = 1012,
+    SemaphoreCreate = 1020,
+    SemaphoreUp = 1021,
+    SemaphoreDown = 1022,
+    CondvarCreate = 1030,
+    CondvarSignal = 1031,
+    CondvarWait = 1032,
+}
+
+pub(crate) fn sys_close(fd: usize) -> isize {
+    syscall(Syscall::Close.into(), [fd, 0, 0])
+}
+
+pub(crate) fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(Syscall::Lseek.into(), [fd, offset as usize, whence])
+}
+
+pub(crate) fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
+    syscall(Syscall::Read.into(), [fd, buf as usize, len])
 }
 
 pub(crate) fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
@@ -37,6 +102,163 @@ pub(crate) fn sys_sched_yield() -> isize {
     syscall(Syscall::SchedYield.into(), [0, 0, 0])
 }
 
-pub fn sys_get_time() -> isize {
-    syscall(Syscall::GetTime.into(), [0, 0, 0])
-}
\ No newline at end of file
+pub(crate) fn sys_nanosleep(req: *const TimeSpec) -> isize {
+    syscall(Syscall::Nanosleep.into(), [req as usize, 0, 0])
+}
+
+pub(crate) fn sys_clock_gettime(clock_id: usize, ts: *mut TimeSpec) -> isize {
+    syscall(Syscall::ClockGettime.into(), [clock_id, ts as usize, 0])
+}
+
+pub(crate) fn sys_gettimeofday(tv: *mut TimeVal) -> isize {
+    syscall(Syscall::Gettimeofday.into(), [tv as usize, 0, 0])
+}
+
+pub(crate) fn sys_mmap(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(Syscall::Mmap.into(), [start, len, prot, flags, fd, offset])
+}
+
+pub(crate) fn sys_msync(start: usize, len: usize) -> isize {
+    syscall(Syscall::Msync.into(), [start, len, 0])
+}
+
+pub(crate) fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(Syscall::Munmap.into(), [start, len, 0])
+}
+
+pub(crate) fn sys_memfd_create(name: *const u8, flags: usize) -> isize {
+    syscall(Syscall::MemfdCreate.into(), [name as usize, flags, 0])
+}
+
+pub(crate) fn sys_shm_create(size: usize) -> isize {
+    syscall(Syscall::ShmCreate.into(), [size, 0, 0])
+}
+
+pub(crate) fn sys_shm_destroy(handle: usize) -> isize {
+    syscall(Syscall::ShmDestroy.into(), [handle, 0, 0])
+}
+
+pub(crate) fn sys_shm_attach(handle: usize, start: usize, prot: usize) -> isize {
+    syscall(Syscall::ShmAttach.into(), [handle, start, prot])
+}
+
+pub(crate) fn sys_shm_detach(start: usize) -> isize {
+    syscall(Syscall::ShmDetach.into(), [start, 0, 0])
+}
+
+pub(crate) fn sys_socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    syscall(Syscall::Socket.into(), [domain, ty, protocol])
+}
+
+pub(crate) fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(Syscall::Bind.into(), [fd, addr as usize, addrlen])
+}
+
+pub(crate) fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(Syscall::Listen.into(), [fd, backlog, 0])
+}
+
+pub(crate) fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(
+        Syscall::Accept.into(),
+        [fd, addr as usize, addrlen as usize],
+    )
+}
+
+pub(crate) fn sys_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(Syscall::Connect.into(), [fd, addr as usize, addrlen])
+}
+
+pub(crate) fn sys_send(
+    fd: usize,
+    buf: *const u8,
+    len: usize,
+    flags: usize,
+    addr: *const u8,
+    addrlen: usize,
+) -> isize {
+    syscall6(
+        Syscall::Send.into(),
+        [fd, buf as usize, len, flags, addr as usize, addrlen],
+    )
+}
+
+pub(crate) fn sys_recv(
+    fd: usize,
+    buf: *mut u8,
+    len: usize,
+    flags: usize,
+    addr: *mut u8,
+    addrlen: *mut u32,
+) -> isize {
+    syscall6(
+        Syscall::Recv.into(),
+        [
+            fd,
+            buf as usize,
+            len,
+            flags,
+            addr as usize,
+            addrlen as usize,
+        ],
+    )
+}
+
+pub(crate) fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    syscall(Syscall::EnableDeadlockDetect.into(), [enabled, 0, 0])
+}
+
+pub(crate) fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    syscall(Syscall::ThreadCreate.into(), [entry, arg, 0])
+}
+
+pub(crate) fn sys_gettid() -> isize {
+    syscall(Syscall::Gettid.into(), [0, 0, 0])
+}
+
+pub(crate) fn sys_waittid(tid: usize) -> isize {
+    syscall(Syscall::Waittid.into(), [tid, 0, 0])
+}
+
+pub(crate) fn sys_mutex_create() -> isize {
+    syscall(Syscall::MutexCreate.into(), [0, 0, 0])
+}
+
+pub(crate) fn sys_mutex_lock(id: usize) -> isize {
+    syscall(Syscall::MutexLock.into(), [id, 0, 0])
+}
+
+pub(crate) fn sys_mutex_unlock(id: usize) -> isize {
+    syscall(Syscall::MutexUnlock.into(), [id, 0, 0])
+}
+
+pub(crate) fn sys_semaphore_create(res_count: usize) -> isize {
+    syscall(Syscall::SemaphoreCreate.into(), [res_count, 0, 0])
+}
+
+pub(crate) fn sys_semaphore_up(sem_id: usize) -> isize {
+    syscall(Syscall::SemaphoreUp.into(), [sem_id, 0, 0])
+}
+
+pub(crate) fn sys_semaphore_down(sem_id: usize) -> isize {
+    syscall(Syscall::SemaphoreDown.into(), [sem_id, 0, 0])
+}
+
+pub(crate) fn sys_condvar_create() -> isize {
+    syscall(Syscall::CondvarCreate.into(), [0, 0, 0])
+}
+
+pub(crate) fn sys_condvar_signal(condvar_id: usize) -> isize {
+    syscall(Syscall::CondvarSignal.into(), [condvar_id, 0, 0])
+}
+
+pub(crate) fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    syscall(Syscall::CondvarWait.into(), [condvar_id, mutex_id, 0])
+}
@@ -0,0 +1,57 @@
+//! A small `std::thread`-like layer over `sys_thread_create` and `sys_waittid`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{exit, sched_yield, thread_create, waittid};
+
+/// What a new thread needs to start, living on the stack of its parent
+/// until the thread has copied it.
+struct ThreadStart {
+    f: fn(usize) -> i32,
+    arg: usize,
+    started: AtomicBool,
+}
+
+/// Entry point of every thread created by [`spawn`].
+extern "C" fn thread_start(start: *const ThreadStart) -> ! {
+    let (f, arg) = unsafe {
+        let start = &*start;
+        let f_arg = (start.f, start.arg);
+        start.started.store(true, Ordering::Release);
+        f_arg
+    };
+    exit(f(arg) as isize);
+    unreachable!()
+}
+
+/// Owned permission to join a thread.
+pub struct JoinHandle {
+    tid: usize,
+}
+
+impl JoinHandle {
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Wait for the thread to finish and return its exit code.
+    pub fn join(self) -> i32 {
+        waittid(self.tid) as i32
+    }
+}
+
+/// Run `f(arg)` in a new thread of the current process.
+pub fn spawn(f: fn(usize) -> i32, arg: usize) -> JoinHandle {
+    let start = ThreadStart {
+        f,
+        arg,
+        started: AtomicBool::new(false),
+    };
+    let tid = thread_create(thread_start as usize, &start as *const _ as usize);
+    assert!(tid >= 0, "thread_create failed");
+    // `start` must outlive the copy made by the new thread
+    while !start.started.load(Ordering::Acquire) {
+        sched_yield();
+    }
+    JoinHandle { tid: tid as usize }
+}
@@ -7,3 +7,5 @@ pub(crate) const PA_WIDTH: usize = 56;
 pub(crate) const VA_WIDTH: usize = 39;
 pub(crate) const PPN_WIDTH: usize = PA_WIDTH - PAGE_SIZE_BITS;
 pub(crate) const VPN_WIDTH: usize = VA_WIDTH - PAGE_SIZE_BITS;
+/// Page table levels of the paging mode, 9 bits of VPN each.
+pub(crate) const PAGE_LEVELS: usize = VPN_WIDTH / 9;
@@ -1,12 +1,21 @@
-use core::ops::{Add, Sub};
+use core::{
+    mem::size_of,
+    ops::{Add, Sub},
+};
 
 use bytemuck::{Pod, Zeroable};
+use riscv::register::satp;
 
 use crate::{
-    arch::config::{PA_WIDTH, PPN_WIDTH, VA_WIDTH, VPN_WIDTH},
-    config::{KERNEL_LOADED_OFFSET_VADDR, PAGE_SIZE, PAGE_SIZE_BITS, PHYS_MEM_BASE_VADDR},
+    arch::config::{PAGE_LEVELS, PA_WIDTH, PPN_WIDTH, VA_WIDTH, VPN_WIDTH},
+    config::{
+        ENTRY_COUNT, KERNEL_LOADED_OFFSET_VADDR, PAGE_SIZE, PAGE_SIZE_BITS, PHYS_MEM_BASE_VADDR,
+    },
 };
 
+const PTE_VALID: usize = 1 << 0;
+const PTE_RWX: usize = 0b111 << 1;
+
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Pod, Zeroable, Debug)]
 #[repr(C)]
 pub(crate) struct PhysAddr(pub(crate) usize);
@@ -106,9 +115,36 @@ impl From<VirtAddr> for PhysAddr {
         if (PHYS_MEM_BASE_VADDR.0..=KERNEL_LOADED_OFFSET_VADDR.0).contains(&addr.0) {
             Self(addr.0 - PHYS_MEM_BASE_VADDR.0)
         } else {
-            unimplemented!("Page Wark")
+            page_walk(addr).unwrap_or_else(|| panic!("{:#x} is not mapped", addr.0))
+        }
+    }
+}
+
+/// Translate `addr` through the page table loaded in `satp`. Like every other
+/// frame, page tables are read at their physical address, which processos
+/// runs identity-mapped.
+fn page_walk(addr: VirtAddr) -> Option<PhysAddr> {
+    let satp = satp::read();
+    if satp.mode() == satp::Mode::Bare {
+        return Some(PhysAddr(addr.0));
+    }
+
+    let mut table = PhysAddr::from(PhysPageNum::from(satp.ppn()));
+    for level in (0..PAGE_LEVELS).rev() {
+        let shift = PAGE_SIZE_BITS + 9 * level;
+        let index = (addr.0 >> shift) & (ENTRY_COUNT - 1);
+        let pte = unsafe { *((table.0 + index * size_of::<usize>()) as *const usize) };
+        if pte & PTE_VALID == 0 {
+            return None;
+        }
+        let next = PhysAddr::from(PhysPageNum::from(pte >> 10));
+        // a leaf, possibly a huge page
+        if pte & PTE_RWX != 0 {
+            return Some(next + (addr.0 & ((1 << shift) - 1)));
         }
+        table = next;
     }
+    None
 }
 
 impl From<PhysAddr> for VirtAddr {